@@ -0,0 +1,97 @@
+// Known top-level config keys.
+pub const ROOT_KEYWORDS: [&str; 8] = [
+    "shell", "mixins", "env", "eval_env", "before", "init", "commands", "version",
+];
+
+// Known command keys.
+pub const COMMAND_KEYWORDS: [&str; 14] = [
+    "description",
+    "depends",
+    "options",
+    "env",
+    "eval_env",
+    "checksum",
+    "persist_checksum",
+    "cmd",
+    "after",
+    "ref",
+    "args",
+    "work_dir",
+    "shell",
+    "group",
+];
+
+// Keys still supported by lets but scheduled for removal.
+pub const DEPRECATED_KEYWORDS: [&str; 1] = ["eval_env"];
+
+// Env variables injected by lets into every command at runtime.
+const COMMAND_ENV: [(&str, &str); 6] = [
+    ("LETS_COMMAND_NAME", "Name of the command being run."),
+    (
+        "LETS_COMMAND_ARGS",
+        "Positional arguments passed to the command, e.g. `lets test -- --foo` gives `--foo`.",
+    ),
+    (
+        "LETS_COMMAND_WORK_DIR",
+        "Directory the command runs in (see `work_dir`).",
+    ),
+    ("LETS_CONFIG", "Name of the config file, e.g. `lets.yaml`."),
+    (
+        "LETS_CONFIG_DIR",
+        "Absolute path to the directory with the config file.",
+    ),
+    ("LETS_SHELL", "Shell used to run the command (see `shell`)."),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BuiltinEnv {
+    pub name: String,
+    pub doc: String,
+}
+
+// Return builtin env available in a command.
+// checksum_names: names of checksum groups if command declares `checksum`
+pub fn builtin_env(checksum_names: Option<&[String]>) -> Vec<BuiltinEnv> {
+    let mut env: Vec<BuiltinEnv> = COMMAND_ENV
+        .iter()
+        .map(|(name, doc)| BuiltinEnv {
+            name: name.to_string(),
+            doc: doc.to_string(),
+        })
+        .collect();
+
+    if let Some(names) = checksum_names {
+        env.push(BuiltinEnv {
+            name: "LETS_CHECKSUM".to_string(),
+            doc: "Checksum of all files listed in `checksum`.".to_string(),
+        });
+        env.extend(names.iter().map(|name| BuiltinEnv {
+            name: format!("LETS_CHECKSUM_{}", name.to_uppercase()),
+            doc: format!("Checksum of files listed in `checksum.{name}`."),
+        }));
+    }
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_env_checksum() {
+        let names = |env: Vec<BuiltinEnv>| -> Vec<String> {
+            env.into_iter()
+                .map(|var| var.name)
+                .filter(|name| name.starts_with("LETS_CHECKSUM"))
+                .collect()
+        };
+
+        assert!(names(builtin_env(None)).is_empty());
+        assert_eq!(names(builtin_env(Some(&[]))), vec!["LETS_CHECKSUM"]);
+        assert_eq!(
+            names(builtin_env(Some(&["deps".to_string()]))),
+            vec!["LETS_CHECKSUM", "LETS_CHECKSUM_DEPS"]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind, Uri,
+};
+
+use crate::treesitter::{Command, CommandReference, Parser};
+
+// Config documents as (uri, text), e.g. the config and its mixins.
+pub type Documents = [(String, String)];
+
+fn item(uri: &str, command: &Command) -> Option<CallHierarchyItem> {
+    Some(CallHierarchyItem {
+        name: command.name.clone(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: None,
+        uri: uri.parse::<Uri>().ok()?,
+        range: command.range,
+        selection_range: command.range,
+        data: None,
+    })
+}
+
+// Find where the command is defined among the documents.
+fn find_item(parser: &Parser, documents: &Documents, name: &str) -> Option<CallHierarchyItem> {
+    documents.iter().find_map(|(uri, doc)| {
+        let command = parser
+            .get_commands(doc)
+            .into_iter()
+            .find(|command| command.name == name)?;
+        item(uri, &command)
+    })
+}
+
+// Group references by key keeping the order of the first occurrence.
+fn group_by<K: PartialEq>(
+    references: impl Iterator<Item = (K, CommandReference)>,
+) -> Vec<(K, Vec<Range>)> {
+    let mut groups: Vec<(K, Vec<Range>)> = vec![];
+    for (key, reference) in references {
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, ranges)) => ranges.push(reference.range),
+            None => groups.push((key, vec![reference.range])),
+        }
+    }
+    groups
+}
+
+// Command under cursor: its name in `commands` or its usage in `depends` and `ref`.
+pub fn prepare(documents: &Documents, uri: &str, pos: &Position) -> Option<Vec<CallHierarchyItem>> {
+    let parser = Parser::new();
+    let (_, doc) = documents.iter().find(|(doc_uri, _)| doc_uri == uri)?;
+    let contains = |range: &Range| range.start <= *pos && *pos <= range.end;
+    let name = parser
+        .get_commands(doc)
+        .into_iter()
+        .find(|command| contains(&command.range))
+        .map(|command| command.name)
+        .or_else(|| {
+            parser
+                .get_command_references(doc)
+                .into_iter()
+                .find(|reference| contains(&reference.range))
+                .map(|reference| reference.name)
+        })?;
+    Some(vec![find_item(&parser, documents, &name)?])
+}
+
+// Commands which the command depends on.
+pub fn outgoing_calls(
+    documents: &Documents,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let parser = Parser::new();
+    let Some((_, doc)) = documents.iter().find(|(uri, _)| *uri == item.uri.as_str()) else {
+        return vec![];
+    };
+    let references = parser
+        .get_command_references(doc)
+        .into_iter()
+        .filter(|reference| reference.command == item.name)
+        .map(|reference| (reference.name.clone(), reference));
+    group_by(references)
+        .into_iter()
+        .filter_map(|(name, from_ranges)| {
+            Some(CallHierarchyOutgoingCall {
+                to: find_item(&parser, documents, &name)?,
+                from_ranges,
+            })
+        })
+        .collect()
+}
+
+// Commands which depend on the command, in all documents.
+pub fn incoming_calls(
+    documents: &Documents,
+    item: &CallHierarchyItem,
+) -> Vec<CallHierarchyIncomingCall> {
+    let parser = Parser::new();
+    let mut calls = vec![];
+    for (uri, doc) in documents {
+        let references = parser
+            .get_command_references(doc)
+            .into_iter()
+            .filter(|reference| reference.name == item.name)
+            .map(|reference| (reference.command.clone(), reference));
+        let commands = parser.get_commands(doc);
+        for (name, from_ranges) in group_by(references) {
+            let from = commands
+                .iter()
+                .find(|command| command.name == name)
+                .and_then(|command| self::item(uri, command));
+            if let Some(from) = from {
+                calls.push(CallHierarchyIncomingCall { from, from_ranges });
+            }
+        }
+    }
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documents() -> Vec<(String, String)> {
+        vec![
+            (
+                "file:///project/lets.yaml".to_string(),
+                r#"
+mixins: [lets.db.yaml]
+commands:
+  deploy:
+    depends: [build, migrate]
+    cmd: echo Deploy
+  build:
+    cmd: echo Build
+  release:
+    ref: deploy"#
+                    .trim()
+                    .to_string(),
+            ),
+            (
+                "file:///project/lets.db.yaml".to_string(),
+                r#"
+commands:
+  migrate:
+    depends: [build]
+    cmd: echo Migrate"#
+                    .trim()
+                    .to_string(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_outgoing_calls() {
+        let documents = documents();
+        let items = prepare(
+            &documents,
+            "file:///project/lets.yaml",
+            &Position::new(2, 3),
+        )
+        .unwrap();
+        assert_eq!(items[0].name, "deploy");
+
+        let calls: Vec<(String, String, u32)> = outgoing_calls(&documents, &items[0])
+            .into_iter()
+            .map(|call| {
+                (
+                    call.to.name,
+                    call.to.uri.to_string(),
+                    call.from_ranges[0].start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (
+                    "build".to_string(),
+                    "file:///project/lets.yaml".to_string(),
+                    3
+                ),
+                (
+                    "migrate".to_string(),
+                    "file:///project/lets.db.yaml".to_string(),
+                    3
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incoming_calls() {
+        let documents = documents();
+        // cursor on `build` in depends of `deploy`
+        let items = prepare(
+            &documents,
+            "file:///project/lets.yaml",
+            &Position::new(3, 16),
+        )
+        .unwrap();
+        assert_eq!(items[0].name, "build");
+        assert_eq!(items[0].range.start.line, 5);
+
+        let calls: Vec<(String, String)> = incoming_calls(&documents, &items[0])
+            .into_iter()
+            .map(|call| (call.from.name, call.from.uri.to_string()))
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (
+                    "deploy".to_string(),
+                    "file:///project/lets.yaml".to_string()
+                ),
+                (
+                    "migrate".to_string(),
+                    "file:///project/lets.db.yaml".to_string()
+                ),
+            ]
+        );
+    }
+}
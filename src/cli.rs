@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+
+use crate::diagnostics::{document_diagnostics, ExternalLinter};
+use crate::graph::{DependencyGraph, GraphFormat};
+use crate::logging;
+use crate::report::{is_error, render, FileDiagnostics, ReportFormat};
+use crate::state::{path_to_uri, uri_to_path, State};
+use crate::transport::Transport;
+use crate::treesitter::Parser;
+
+// Version reported by `--version` and in `serverInfo`.
+pub const VERSION: &str = if cfg!(debug_assertions) {
+    concat!(env!("CARGO_PKG_VERSION"), "-debug")
+} else {
+    env!("CARGO_PKG_VERSION")
+};
+
+// Without a subcommand the server talks LSP over stdio.
+#[derive(Debug, clap::Parser)]
+#[command(name = "lets_ls", version = VERSION, about = "Lsp server for lets task runner")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+    /// Communicate over stdin and stdout (default)
+    #[arg(long, conflicts_with = "tcp")]
+    pub stdio: bool,
+    /// Listen on the port of localhost and serve a single client
+    #[arg(long, value_name = "PORT")]
+    pub tcp: Option<u16>,
+    /// Listen on the unix socket and serve a single client
+    #[cfg(unix)]
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stdio", "tcp"])]
+    pub socket: Option<PathBuf>,
+    /// Write logs to the file instead of stderr
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    /// Log level, overrides `RUST_LOG`
+    #[arg(long, value_enum, global = true)]
+    pub log_level: Option<LogLevel>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => log::LevelFilter::Off,
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+impl Cli {
+    pub fn transport(&self) -> Transport {
+        if let Some(port) = self.tcp {
+            return Transport::Tcp(port);
+        }
+        #[cfg(unix)]
+        if let Some(path) = &self.socket {
+            return Transport::Socket(path.clone());
+        }
+        Transport::Stdio
+    }
+
+    // Logs go to stderr by default since stdout may be used by the LSP session.
+    pub fn init_logging(&self) -> std::io::Result<()> {
+        let mut builder = env_logger::Builder::from_default_env();
+        if let Some(level) = self.log_level {
+            builder.filter_level(level.into());
+        }
+        if let Some(path) = &self.log_file {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            builder.target(env_logger::Target::Pipe(Box::new(file)));
+        }
+        logging::init(&mut builder);
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum CliCommand {
+    /// Check the config and its mixins and print diagnostics, exit with 1 if there are errors
+    Check {
+        /// Lint `cmd` scripts with the program, e.g. `shellcheck`
+        #[arg(long)]
+        linter: Option<String>,
+        #[arg(long, value_enum, default_value_t)]
+        format: ReportFormat,
+        /// Path to the config or one of its mixins
+        #[arg(default_value = "lets.yaml")]
+        path: PathBuf,
+    },
+    /// Print graph of `depends` and `ref` between commands, including mixins
+    Graph {
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat,
+        /// Path to the config or one of its mixins
+        #[arg(default_value = "lets.yaml")]
+        path: PathBuf,
+    },
+}
+
+pub(crate) fn file_uri(path: &Path) -> std::io::Result<String> {
+    let path = std::fs::canonicalize(path)?;
+    path_to_uri(&path).ok_or_else(|| std::io::Error::other("path can not be converted to uri"))
+}
+
+// Path of the document to print, relative to the current directory if possible.
+fn display_path(uri: &str) -> String {
+    let Some(path) = uri_to_path(uri) else {
+        return uri.to_string();
+    };
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(&path)
+        .display()
+        .to_string()
+}
+
+fn check(
+    path: &Path,
+    linter: Option<ExternalLinter>,
+    format: ReportFormat,
+) -> Result<i32, std::io::Error> {
+    let uri = file_uri(path)?;
+    let state = State::new();
+    let root = state.find_root_config(&uri);
+    let documents = state.get_config_documents(&root);
+    let complete = !state.has_unresolved_mixins(&root);
+    let graph = DependencyGraph::from_documents(&documents);
+    let shell = state
+        .read_document(&root)
+        .and_then(|doc| Parser::new().get_shell(&doc));
+    let linter = linter.map(|linter| linter.for_shell(shell.as_deref()));
+
+    let files: Vec<FileDiagnostics> = documents
+        .iter()
+        .map(|(uri, doc)| {
+            let diagnostics = document_diagnostics(doc, &graph, complete, linter.as_ref());
+            (display_path(uri), diagnostics)
+        })
+        .collect();
+    print!("{}", render(format, &files));
+    let has_errors = files
+        .iter()
+        .any(|(_, diagnostics)| diagnostics.iter().any(is_error));
+    Ok(if has_errors { 1 } else { 0 })
+}
+
+// Run the subcommand and return the exit code.
+pub fn run(command: CliCommand) -> i32 {
+    match command {
+        CliCommand::Check {
+            linter,
+            format,
+            path,
+        } => match check(&path, linter.as_deref().map(ExternalLinter::new), format) {
+            Ok(code) => code,
+            Err(err) => {
+                eprintln!("lets_ls: {}: {}", path.display(), err);
+                2
+            }
+        },
+        CliCommand::Graph { format, path } => {
+            let uri = match file_uri(&path) {
+                Ok(uri) => uri,
+                Err(err) => {
+                    eprintln!("lets_ls: {}: {}", path.display(), err);
+                    return 2;
+                }
+            };
+            let state = State::new();
+            let documents = state.get_config_documents(&state.find_root_config(&uri));
+            let graph = DependencyGraph::from_documents(&documents);
+            print!("{}", graph.render(format));
+            0
+        }
+    }
+}
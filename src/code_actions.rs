@@ -0,0 +1,1222 @@
+use std::collections::HashMap;
+
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, NumberOrString, Position, Range,
+    TextEdit, Uri, WorkspaceEdit,
+};
+
+use tree_sitter::{Node, Point};
+
+use crate::diagnostics::{suggest_commands, UNDEFINED_DEPENDENCY};
+use crate::shell::Script;
+use crate::treesitter::{
+    find_command_mapping, find_mapping_value, find_root_mapping, get_node_text, get_pair_key,
+    node_range, Parser, ReferenceKind,
+};
+
+fn ranges_overlap(a: &Range, b: &Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+fn workspace_edit(uri: &Uri, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    }
+}
+
+// Diagnostics from the request context reported for the range.
+fn diagnostics_for(diagnostics: &[Diagnostic], code: &str, range: &Range) -> Vec<Diagnostic> {
+    diagnostics
+        .iter()
+        .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+        .filter(|d| ranges_overlap(&d.range, range))
+        .cloned()
+        .collect()
+}
+
+// Offer to create commands that are used in `depends` within range but do not exist.
+pub fn create_command_actions(
+    doc: &str,
+    uri: &Uri,
+    range: &Range,
+    known_commands: &[String],
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    let parser = Parser::new();
+    let mut actions = vec![];
+    let mut created: Vec<String> = vec![];
+    for reference in parser.get_command_references(doc) {
+        if reference.kind != ReferenceKind::Depends
+            || known_commands.contains(&reference.name)
+            || created.contains(&reference.name)
+            || !ranges_overlap(&reference.range, range)
+        {
+            continue;
+        }
+        let body = [(0, "description: TODO"), (0, "cmd: echo \"TODO\"")];
+        let Some(edit) = new_command_edit(&parser, doc, &reference.name, &body) else {
+            continue;
+        };
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Create command `{}`", reference.name),
+            kind: Some(CodeActionKind::QUICKFIX),
+            diagnostics: Some(diagnostics_for(
+                diagnostics,
+                UNDEFINED_DEPENDENCY,
+                &reference.range,
+            )),
+            edit: Some(workspace_edit(uri, vec![edit])),
+            ..Default::default()
+        }));
+        created.push(reference.name);
+    }
+    actions
+}
+
+// Offer to replace misspelled `depends` and `ref` items with similar known commands.
+pub fn did_you_mean_actions(
+    doc: &str,
+    uri: &Uri,
+    range: &Range,
+    known_commands: &[String],
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeActionOrCommand> {
+    let mut actions = vec![];
+    for reference in Parser::new().get_command_references(doc) {
+        if known_commands.contains(&reference.name) || !ranges_overlap(&reference.range, range) {
+            continue;
+        }
+        let suggestions = suggest_commands(&reference.name, known_commands);
+        for (i, suggestion) in suggestions.into_iter().enumerate() {
+            let edit = TextEdit {
+                range: reference.range,
+                new_text: suggestion.clone(),
+            };
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Change to `{suggestion}`"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(diagnostics_for(
+                    diagnostics,
+                    UNDEFINED_DEPENDENCY,
+                    &reference.range,
+                )),
+                edit: Some(workspace_edit(uri, vec![edit])),
+                is_preferred: Some(i == 0),
+                ..Default::default()
+            }));
+        }
+    }
+    actions
+}
+
+// Part of a sequence which has to be kept when converting between flow and block styles.
+#[derive(Debug)]
+enum SequenceEntry<'a> {
+    Item(String),
+    // comment text and whether it is on the same line as the preceding entry
+    Comment(&'a str, bool),
+}
+
+// Offer to convert `mixins`, `depends`, `checksum` and `cmd` lists under cursor
+// between flow (`[a, b]`) and block (`- a`) styles.
+pub fn convert_sequence_actions(doc: &str, uri: &Uri, range: &Range) -> Vec<CodeActionOrCommand> {
+    let tree = Parser::new().parse(doc);
+    let point = Point::new(range.start.line as usize, range.start.character as usize);
+    let Some(node) = tree.root_node().descendant_for_point_range(point, point) else {
+        return vec![];
+    };
+
+    let Some((pair, key)) = std::iter::successors(Some(node), |node| node.parent())
+        .filter(|node| node.kind() == "block_mapping_pair")
+        .find_map(|pair| convertible_sequence_key(&pair, doc).map(|key| (pair, key)))
+    else {
+        return vec![];
+    };
+    let Some(value) = pair.child_by_field_name("value") else {
+        return vec![];
+    };
+    let Some(sequence) = value.named_child(0) else {
+        return vec![];
+    };
+
+    let (style, edits) = match sequence.kind() {
+        "flow_sequence" => ("block", to_block_sequence(&pair, &value, &sequence, doc)),
+        _ => ("flow", to_flow_sequence(&pair, &sequence, doc)),
+    };
+    let Some(edits) = edits else {
+        return vec![];
+    };
+
+    vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Convert `{key}` to {style} style"),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(workspace_edit(uri, edits)),
+        ..Default::default()
+    })]
+}
+
+// Return key of the pair if its value is a list which may be converted.
+fn convertible_sequence_key<'a>(pair: &Node, doc: &'a str) -> Option<&'a str> {
+    let value = pair.child_by_field_name("value")?.named_child(0)?;
+    if value.kind() != "flow_sequence" && value.kind() != "block_sequence" {
+        return None;
+    }
+
+    // keys from the root of the document to the pair
+    let mut path = vec![];
+    let mut current = Some(*pair);
+    while let Some(node) = current {
+        if node.kind() == "block_mapping_pair" {
+            path.push(get_pair_key(&node, doc)?);
+        }
+        current = node.parent();
+    }
+    path.reverse();
+
+    match path.as_slice() {
+        ["mixins"] => Some("mixins"),
+        ["commands", _, key @ ("depends" | "checksum" | "cmd")] => Some(key),
+        ["commands", _, "checksum", key] => Some(key),
+        _ => None,
+    }
+}
+
+// Collect items and comments of the sequence. Comments between the key and the value
+// are included too. Return None if some item can not be moved to another style.
+fn sequence_entries<'a>(
+    pair: &Node,
+    sequence: &Node,
+    doc: &'a str,
+    to_flow: bool,
+) -> Option<Vec<SequenceEntry<'a>>> {
+    let mut entries = vec![];
+    let mut last_row = pair.start_position().row;
+    let mut cursor = pair.walk();
+    let head_comments = pair
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "comment")
+        .collect::<Vec<_>>();
+    let mut cursor = sequence.walk();
+    let children = sequence.children(&mut cursor).collect::<Vec<_>>();
+
+    for child in head_comments.iter().chain(children.iter()) {
+        match child.kind() {
+            "comment" => {
+                let same_line = child.start_position().row == last_row;
+                entries.push(SequenceEntry::Comment(
+                    get_node_text(child, doc)?,
+                    same_line,
+                ));
+            }
+            "flow_node" => entries.push(SequenceEntry::Item(item_text(child, doc, to_flow)?)),
+            "block_sequence_item" => {
+                entries.push(SequenceEntry::Item(item_text(
+                    &child.named_child(0)?,
+                    doc,
+                    to_flow,
+                )?));
+            }
+            _ => {}
+        }
+        last_row = child.end_position().row;
+    }
+    Some(entries)
+}
+
+// Return text of the item suitable for the target style.
+fn item_text(node: &Node, doc: &str, to_flow: bool) -> Option<String> {
+    if node.kind() != "flow_node" || node.start_position().row != node.end_position().row {
+        return None;
+    }
+    let text = get_node_text(node, doc)?;
+    let is_plain = node.named_child(0)?.kind() == "plain_scalar";
+    if to_flow && is_plain && text.contains([',', '[', ']', '{', '}']) {
+        // these characters are indicators inside flow collections
+        return Some(format!(
+            "\"{}\"",
+            text.replace('\\', "\\\\").replace('"', "\\\"")
+        ));
+    }
+    Some(text.to_string())
+}
+
+// Position right after the `:` of the pair.
+fn colon_end(pair: &Node) -> Option<Position> {
+    let mut cursor = pair.walk();
+    let colon = pair
+        .children(&mut cursor)
+        .find(|child| child.kind() == ":")?;
+    Some(node_range(&colon).end)
+}
+
+fn line_end(doc: &str, row: usize) -> Position {
+    let len = doc.lines().nth(row).map(|line| line.len()).unwrap_or(0);
+    Position::new(row as u32, len as u32)
+}
+
+// `key: [a, b] # comment` -> `key: # comment\n  - a\n  - b`
+fn to_block_sequence(
+    pair: &Node,
+    value: &Node,
+    sequence: &Node,
+    doc: &str,
+) -> Option<Vec<TextEdit>> {
+    let indent = " ".repeat(pair.start_position().column + 2);
+    let mut text = String::new();
+    for entry in sequence_entries(pair, sequence, doc, false)? {
+        match entry {
+            SequenceEntry::Item(item) => text.push_str(&format!("\n{indent}- {item}")),
+            SequenceEntry::Comment(comment, true) => text.push_str(&format!(" {comment}")),
+            SequenceEntry::Comment(comment, false) => {
+                text.push_str(&format!("\n{indent}{comment}"))
+            }
+        }
+    }
+
+    // drop the flow sequence and put items after the rest of the line, e.g. trailing comment
+    let start = value
+        .prev_sibling()
+        .map(|prev| node_range(&prev).end)
+        .or_else(|| colon_end(pair))?;
+    let end = node_range(value).end;
+    let line_end = line_end(doc, value.end_position().row);
+    let edits = if line_end == end {
+        vec![TextEdit {
+            range: Range::new(start, end),
+            new_text: text,
+        }]
+    } else {
+        vec![
+            TextEdit {
+                range: Range::new(start, end),
+                new_text: String::new(),
+            },
+            TextEdit {
+                range: Range::new(line_end, line_end),
+                new_text: text,
+            },
+        ]
+    };
+    Some(edits)
+}
+
+// `key:\n  - a\n  - b` -> `key: [a, b]`, multiline flow sequence is used to keep comments.
+fn to_flow_sequence(pair: &Node, sequence: &Node, doc: &str) -> Option<Vec<TextEdit>> {
+    let entries = sequence_entries(pair, sequence, doc, true)?;
+    let has_comments = entries
+        .iter()
+        .any(|entry| matches!(entry, SequenceEntry::Comment(..)));
+
+    let text = if has_comments {
+        let indent = " ".repeat(pair.start_position().column + 2);
+        let last_item = entries
+            .iter()
+            .rposition(|entry| matches!(entry, SequenceEntry::Item(_)));
+        let mut text = " [".to_string();
+        for (i, entry) in entries.iter().enumerate() {
+            match entry {
+                SequenceEntry::Item(item) => {
+                    let comma = if Some(i) == last_item { "" } else { "," };
+                    text.push_str(&format!("\n{indent}{item}{comma}"));
+                }
+                SequenceEntry::Comment(comment, true) => text.push_str(&format!(" {comment}")),
+                SequenceEntry::Comment(comment, false) => {
+                    text.push_str(&format!("\n{indent}{comment}"))
+                }
+            }
+        }
+        if matches!(entries.last(), Some(SequenceEntry::Comment(..))) {
+            text.push_str(&format!("\n{indent}"));
+        }
+        text.push(']');
+        text
+    } else {
+        let items: Vec<String> = entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                SequenceEntry::Item(item) => Some(item),
+                SequenceEntry::Comment(..) => None,
+            })
+            .collect();
+        format!(" [{}]", items.join(", "))
+    };
+
+    let start = colon_end(pair)?;
+    // block sequence ends at the start of the next line, so stop at its last child
+    let end = node_range(&sequence.child(sequence.child_count().checked_sub(1)?)?).end;
+    Some(vec![TextEdit {
+        range: Range::new(start, end),
+        new_text: text,
+    }])
+}
+
+// Offer to move selected lines of multiline `cmd` into a new command, which is either
+// added to `depends` of the original command or called with `lets <new>` in place of the lines.
+pub fn extract_command_actions(doc: &str, uri: &Uri, range: &Range) -> Vec<CodeActionOrCommand> {
+    let parser = Parser::new();
+    let Some(extraction) = Extraction::new(&parser, doc, range) else {
+        return vec![];
+    };
+
+    let mut actions = vec![];
+    // dependencies run before the script and without its `env` and options
+    let add_dependency = if extraction.from_first_line {
+        extraction.add_dependency_edits(&parser, doc)
+    } else {
+        None
+    };
+    let variants = [
+        (
+            format!(
+                "Extract into new command `{}` in `depends` (without `env` and options of `{}`)",
+                extraction.name, extraction.command
+            ),
+            add_dependency,
+        ),
+        (
+            format!(
+                "Extract into new command `{}` called with `lets`",
+                extraction.name
+            ),
+            Some(vec![extraction.call_edit(doc)]),
+        ),
+    ];
+    for (title, edits) in variants {
+        let Some(mut edits) = edits else {
+            continue;
+        };
+        let Some(new_command) = extraction.new_command_edit(&parser, doc) else {
+            continue;
+        };
+        edits.push(new_command);
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title,
+            kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+            edit: Some(workspace_edit(uri, edits)),
+            ..Default::default()
+        }));
+    }
+    actions
+}
+
+// Lines of `cmd` block scalar selected for extraction.
+struct Extraction {
+    // command the lines are extracted from
+    command: String,
+    // name of the new command
+    name: String,
+    // document rows of the selected lines
+    first_row: u32,
+    last_row: u32,
+    // selected lines with script indentation stripped
+    lines: Vec<String>,
+    // leading whitespace of the first selected line in the document
+    indent: String,
+    // selection starts at the first script line
+    from_first_line: bool,
+}
+
+impl Extraction {
+    fn new(parser: &Parser, doc: &str, range: &Range) -> Option<Self> {
+        if range.start == range.end {
+            return None;
+        }
+        let command = parser.get_current_command(doc, &range.start)?.name;
+        let script = parser
+            .get_scripts(doc)
+            .into_iter()
+            .find(|script| script.key == "cmd" && script.contains(&range.start))?;
+
+        // selection ending at the start of a line does not include that line
+        let last_row = if range.end.character == 0 && range.end.line > range.start.line {
+            range.end.line - 1
+        } else {
+            range.end.line
+        };
+        let first = script_line(&script, range.start.line)?;
+        let last = script_line(&script, last_row)?;
+        let script_lines: Vec<&str> = script.text.lines().collect();
+        let is_block = script.lines().first()?.line > script.range.start.line;
+        // keep at least one line in the original command
+        if !is_block || last - first + 1 >= script_lines.len() {
+            return None;
+        }
+
+        let lines: Vec<String> = script_lines[first..=last]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        if lines.iter().all(|line| line.trim().is_empty()) {
+            return None;
+        }
+        let line = doc.lines().nth(range.start.line as usize)?;
+        let indent = line[..line.len() - line.trim_start().len()].to_string();
+
+        let commands: Vec<String> = parser
+            .get_commands(doc)
+            .into_iter()
+            .map(|command| command.name)
+            .collect();
+        let base = format!("{command}-extracted");
+        let name = std::iter::once(base.clone())
+            .chain((2..).map(|i| format!("{base}-{i}")))
+            .find(|name| !commands.contains(name))?;
+
+        Some(Self {
+            command,
+            name,
+            first_row: range.start.line,
+            last_row,
+            lines,
+            indent,
+            from_first_line: first == 0,
+        })
+    }
+
+    // Range of the selected lines including the line break.
+    fn lines_range(&self, doc: &str) -> Range {
+        if (self.last_row as usize + 1) < doc.lines().count() {
+            Range::new(
+                Position::new(self.first_row, 0),
+                Position::new(self.last_row + 1, 0),
+            )
+        } else {
+            // the last line of the document, remove the preceding line break instead
+            let previous = doc.lines().nth(self.first_row as usize - 1).unwrap_or("");
+            let last = doc.lines().nth(self.last_row as usize).unwrap_or("");
+            Range::new(
+                Position::new(self.first_row - 1, previous.len() as u32),
+                Position::new(self.last_row, last.len() as u32),
+            )
+        }
+    }
+
+    fn call_edit(&self, doc: &str) -> TextEdit {
+        let last = doc.lines().nth(self.last_row as usize).unwrap_or("");
+        TextEdit {
+            range: Range::new(
+                Position::new(self.first_row, 0),
+                Position::new(self.last_row, last.len() as u32),
+            ),
+            new_text: format!("{}lets {}", self.indent, self.name),
+        }
+    }
+
+    // Remove selected lines and add new command to `depends` of the original one.
+    fn add_dependency_edits(&self, parser: &Parser, doc: &str) -> Option<Vec<TextEdit>> {
+        let tree = parser.parse(doc);
+        let mapping = find_command_mapping(&tree.root_node(), doc, &self.command)?;
+        let mut cursor = mapping.walk();
+        let pairs: Vec<Node> = mapping.named_children(&mut cursor).collect();
+        let cmd = pairs
+            .iter()
+            .find(|pair| get_pair_key(pair, doc) == Some("cmd"))?;
+        let depends = pairs
+            .iter()
+            .find(|pair| get_pair_key(pair, doc) == Some("depends"));
+
+        let removed_lines = TextEdit {
+            range: self.lines_range(doc),
+            new_text: String::new(),
+        };
+        let dependency = match depends {
+            None => {
+                let pad = " ".repeat(cmd.start_position().column);
+                let position = Position::new(cmd.start_position().row as u32, 0);
+                TextEdit {
+                    range: Range::new(position, position),
+                    new_text: format!("{pad}depends: [{}]\n", self.name),
+                }
+            }
+            Some(depends) => {
+                let sequence = depends.child_by_field_name("value")?.named_child(0)?;
+                match sequence.kind() {
+                    "flow_sequence" => {
+                        let mut cursor = sequence.walk();
+                        let has_items = sequence
+                            .named_children(&mut cursor)
+                            .any(|item| item.kind() == "flow_node");
+                        let close = sequence.child(sequence.child_count().checked_sub(1)?)?;
+                        let position = node_range(&close).start;
+                        let separator = if has_items { ", " } else { "" };
+                        TextEdit {
+                            range: Range::new(position, position),
+                            new_text: format!("{separator}{}", self.name),
+                        }
+                    }
+                    "block_sequence" => {
+                        let mut cursor = sequence.walk();
+                        let last = sequence
+                            .named_children(&mut cursor)
+                            .filter(|item| item.kind() == "block_sequence_item")
+                            .last()?;
+                        let position = node_range(&last).end;
+                        let pad = " ".repeat(last.start_position().column);
+                        TextEdit {
+                            range: Range::new(position, position),
+                            new_text: format!("\n{pad}- {}", self.name),
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+        };
+        Some(vec![dependency, removed_lines])
+    }
+
+    fn new_command_edit(&self, parser: &Parser, doc: &str) -> Option<TextEdit> {
+        let mut body = vec![(0, "cmd: |")];
+        body.extend(self.lines.iter().map(|line| (1, line.as_str())));
+        new_command_edit(parser, doc, &self.name, &body)
+    }
+}
+
+// Return index of the script line at document row.
+fn script_line(script: &Script, row: u32) -> Option<usize> {
+    script.lines().iter().position(|line| line.line == row)
+}
+
+pub const SORT_COMMANDS: &str = "source.sortCommands";
+
+// Lines of a command with comments above it.
+#[derive(Clone)]
+struct CommandChunk<'a> {
+    name: &'a str,
+    has_description: bool,
+    start: usize,
+    end: usize,
+}
+
+// Offer to sort commands by name, or to put commands with `description` first.
+// Each command moves with the comments above it.
+pub fn sort_commands_actions(doc: &str, uri: &Uri) -> Vec<CodeActionOrCommand> {
+    let parser = Parser::new();
+    let tree = parser.parse(doc);
+    let Some(commands) = find_root_mapping(&tree.root_node())
+        .and_then(|root| find_mapping_value(&root, doc, "commands"))
+        .and_then(|commands| commands.named_child(0))
+        .filter(|commands| commands.kind() == "block_mapping")
+    else {
+        return vec![];
+    };
+    // the `commands` pair
+    let Some(commands_pair) = commands.parent().and_then(|node| node.parent()) else {
+        return vec![];
+    };
+
+    let lines: Vec<&str> = doc.lines().collect();
+    let is_blank = |row: usize| lines.get(row).is_none_or(|line| line.trim().is_empty());
+    let mut cursor = commands.walk();
+    let pairs: Vec<Node> = commands
+        .named_children(&mut cursor)
+        .filter(|node| node.kind() == "block_mapping_pair")
+        .collect();
+    let Some(column) = pairs.first().map(|pair| pair.start_position().column) else {
+        return vec![];
+    };
+    // comments aligned with commands at the end of a command belong to the next one
+    let is_outer_comment = |row: usize| {
+        lines.get(row).is_some_and(|line| {
+            let trimmed = line.trim_start();
+            trimmed.starts_with('#') && line.len() - trimmed.len() <= column
+        })
+    };
+
+    let mut chunks = vec![];
+    let mut previous_end = commands_pair.start_position().row;
+    for pair in &pairs {
+        let Some(name) = get_pair_key(pair, doc) else {
+            return vec![];
+        };
+        let mut start = previous_end + 1;
+        while start < pair.start_position().row && is_blank(start) {
+            start += 1;
+        }
+        let mut end = pair.end_position().row;
+        if pair.end_position().column == 0 {
+            end -= 1;
+        }
+        while end > pair.start_position().row && (is_blank(end) || is_outer_comment(end)) {
+            end -= 1;
+        }
+        let has_description = pair
+            .child_by_field_name("value")
+            .and_then(|value| value.named_child(0))
+            .filter(|body| body.kind() == "block_mapping")
+            .and_then(|body| find_mapping_value(&body, doc, "description"))
+            .is_some();
+        chunks.push(CommandChunk {
+            name,
+            has_description,
+            start,
+            end,
+        });
+        previous_end = end;
+    }
+    let (Some(first), Some(last)) = (chunks.first(), chunks.last()) else {
+        return vec![];
+    };
+    let region = (first.start, last.end);
+    let newline = if doc.contains("\r\n") { "\r\n" } else { "\n" };
+    let separator = if chunks
+        .windows(2)
+        .any(|pair| (pair[0].end + 1..pair[1].start).any(is_blank))
+    {
+        newline.repeat(2)
+    } else {
+        newline.to_string()
+    };
+    let has_descriptions = chunks.iter().any(|chunk| chunk.has_description);
+
+    let variants = [
+        ("Sort commands", false),
+        ("Sort commands, with descriptions first", true),
+    ];
+    let mut actions = vec![];
+    // orders already offered, starting with the current one
+    let mut orders = vec![chunks.iter().map(|chunk| chunk.start).collect::<Vec<_>>()];
+    for (title, descriptions_first) in variants {
+        if descriptions_first && !has_descriptions {
+            continue;
+        }
+        let mut sorted = chunks.clone();
+        sorted.sort_by_key(|chunk| (descriptions_first && !chunk.has_description, chunk.name));
+        let order: Vec<usize> = sorted.iter().map(|chunk| chunk.start).collect();
+        if orders.contains(&order) {
+            continue;
+        }
+        orders.push(order);
+        let text = sorted
+            .iter()
+            .map(|chunk| lines[chunk.start..=chunk.end].join(newline))
+            .collect::<Vec<_>>()
+            .join(&separator);
+        let edit = TextEdit {
+            range: Range::new(
+                Position::new(region.0 as u32, 0),
+                Position::new(region.1 as u32, lines[region.1].len() as u32),
+            ),
+            new_text: text,
+        };
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: title.to_string(),
+            kind: Some(CodeActionKind::new(SORT_COMMANDS)),
+            edit: Some(workspace_edit(uri, vec![edit])),
+            ..Default::default()
+        }));
+    }
+    actions
+}
+
+// Insert command after the last command with the same indentation as other commands.
+// body: lines of the command body with their nesting level
+pub fn new_command_edit(
+    parser: &Parser,
+    doc: &str,
+    name: &str,
+    body: &[(usize, &str)],
+) -> Option<TextEdit> {
+    let tree = parser.parse(doc);
+    let commands = find_root_mapping(&tree.root_node())
+        .and_then(|root| find_mapping_value(&root, doc, "commands"))?
+        .named_child(0)
+        .filter(|commands| commands.kind() == "block_mapping")?;
+
+    let mut cursor = commands.walk();
+    let pairs: Vec<_> = commands.named_children(&mut cursor).collect();
+    let first = pairs.first()?;
+    let last = pairs.last()?;
+
+    let indent = first.start_position().column;
+    let body_indent = first
+        .child_by_field_name("value")
+        .filter(|value| value.kind() == "block_node")
+        .map(|value| value.start_position().column.saturating_sub(indent))
+        .filter(|body_indent| *body_indent > 0)
+        .unwrap_or(2);
+
+    // keep blank lines between commands if config uses them
+    let lines: Vec<&str> = doc.lines().collect();
+    let separated = pairs.iter().skip(1).any(|pair| {
+        let row = pair.start_position().row;
+        row > 0
+            && lines
+                .get(row - 1)
+                .is_some_and(|line| line.trim().is_empty())
+    });
+
+    let pad = " ".repeat(indent);
+    let mut stub = format!("{pad}{name}:");
+    for (level, line) in body {
+        if line.trim().is_empty() {
+            stub.push('\n');
+        } else {
+            let body_pad = " ".repeat(indent + body_indent * (level + 1));
+            stub.push_str(&format!("\n{body_pad}{line}"));
+        }
+    }
+    let blank = if separated { "\n" } else { "" };
+
+    let end = last.end_position();
+    let (position, text) = if end.column == 0 {
+        // block values may end at the start of the next line
+        (Position::new(end.row as u32, 0), format!("{blank}{stub}\n"))
+    } else {
+        (
+            Position::new(end.row as u32, end.column as u32),
+            format!("\n{blank}{stub}"),
+        )
+    };
+
+    Some(TextEdit {
+        range: Range::new(position, position),
+        new_text: text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_edit(doc: &str, edit: &TextEdit) -> String {
+        apply_edits(doc, std::slice::from_ref(edit))
+    }
+
+    // Apply non-overlapping edits which refer to the original document.
+    fn apply_edits(doc: &str, edits: &[TextEdit]) -> String {
+        let offset = |pos: &Position| -> usize {
+            doc.split_inclusive('\n')
+                .take(pos.line as usize)
+                .map(|line| line.len())
+                .sum::<usize>()
+                + pos.character as usize
+        };
+        let mut result = doc.to_string();
+        let mut edits = edits.to_vec();
+        edits.sort_by_key(|edit| edit.range.start);
+        for edit in edits.iter().rev() {
+            let start = offset(&edit.range.start);
+            let end = offset(&edit.range.end);
+            result.replace_range(start..end, &edit.new_text);
+        }
+        result
+    }
+
+    // Apply the only conversion action available at position.
+    fn convert(doc: &str, line: u32, character: u32) -> Option<(String, String)> {
+        let uri: Uri = "file:///project/lets.yaml".parse().unwrap();
+        let position = Position::new(line, character);
+        let actions = convert_sequence_actions(doc, &uri, &Range::new(position, position));
+        let CodeActionOrCommand::CodeAction(action) = actions.into_iter().next()? else {
+            panic!("expected code action");
+        };
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        Some((action.title, apply_edits(doc, edits)))
+    }
+
+    #[test]
+    fn test_convert_flow_to_block() {
+        let doc = r#"
+mixins: [lets.build.yaml, lets.test.yaml] # mixins
+commands:
+  test:
+    depends: [build, # first
+      lint]
+    cmd: [go, test, "./..."]
+"#
+        .trim_start();
+
+        let (title, result) = convert(doc, 0, 2).unwrap();
+        assert_eq!(title, "Convert `mixins` to block style");
+        assert_eq!(
+            result,
+            r#"
+mixins: # mixins
+  - lets.build.yaml
+  - lets.test.yaml
+commands:
+  test:
+    depends: [build, # first
+      lint]
+    cmd: [go, test, "./..."]
+"#
+            .trim_start()
+        );
+
+        let (_, result) = convert(doc, 3, 15).unwrap();
+        assert!(result.contains("    depends:\n      - build # first\n      - lint\n"));
+
+        let (title, result) = convert(doc, 5, 20).unwrap();
+        assert_eq!(title, "Convert `cmd` to block style");
+        assert!(result.ends_with("    cmd:\n      - go\n      - test\n      - \"./...\"\n"));
+
+        // not a list
+        assert!(convert(doc, 2, 3).is_none());
+    }
+
+    #[test]
+    fn test_convert_block_to_flow() {
+        let doc = r#"
+commands:
+  test:
+    depends:
+      - build
+      - lint
+    checksum:
+      files:
+        - go.mod
+        - go.sum
+    cmd:
+      - echo
+      - a, b
+  lint:
+    depends: # before lint
+      - build # always
+      # then
+      - gen
+"#
+        .trim_start();
+
+        let (title, result) = convert(doc, 4, 8).unwrap();
+        assert_eq!(title, "Convert `depends` to flow style");
+        assert!(result.contains("    depends: [build, lint]\n    checksum:"));
+
+        let (title, result) = convert(doc, 7, 8).unwrap();
+        assert_eq!(title, "Convert `files` to flow style");
+        assert!(result.contains("      files: [go.mod, go.sum]\n    cmd:"));
+
+        let (_, result) = convert(doc, 10, 4).unwrap();
+        assert!(result.contains("    cmd: [echo, \"a, b\"]\n  lint:"));
+
+        let (_, result) = convert(doc, 15, 8).unwrap();
+        assert!(result.ends_with(
+            "    depends: [ # before lint\n      build, # always\n      # then\n      gen]\n"
+        ));
+    }
+
+    fn extract(doc: &str, range: Range) -> Vec<(String, String)> {
+        let uri: Uri = "file:///project/lets.yaml".parse().unwrap();
+        extract_command_actions(doc, &uri, &range)
+            .into_iter()
+            .map(|action| {
+                let CodeActionOrCommand::CodeAction(action) = action else {
+                    panic!("expected code action");
+                };
+                let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+                (action.title, apply_edits(doc, edits))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_command_actions() {
+        let doc = r#"
+commands:
+  release:
+    depends: [test]
+    cmd: |
+      echo Build
+      go build ./...
+
+      echo Release
+  release-extracted:
+    cmd: echo
+"#
+        .trim_start();
+
+        // select "echo Build" and "go build" lines
+        let range = Range::new(Position::new(4, 2), Position::new(6, 0));
+        let actions = extract(doc, range);
+        assert_eq!(actions.len(), 2);
+
+        assert_eq!(
+            actions[0].0,
+            "Extract into new command `release-extracted-2` in `depends` (without `env` and options of `release`)"
+        );
+        assert_eq!(
+            actions[0].1,
+            r#"
+commands:
+  release:
+    depends: [test, release-extracted-2]
+    cmd: |
+
+      echo Release
+  release-extracted:
+    cmd: echo
+  release-extracted-2:
+    cmd: |
+      echo Build
+      go build ./...
+"#
+            .trim_start()
+        );
+
+        assert_eq!(
+            actions[1].0,
+            "Extract into new command `release-extracted-2` called with `lets`"
+        );
+        assert!(actions[1].1.starts_with(
+            "commands:\n  release:\n    depends: [test]\n    cmd: |\n      lets release-extracted-2\n\n      echo Release\n"
+        ));
+
+        // empty selection or whole script
+        let range = Range::new(Position::new(4, 2), Position::new(4, 2));
+        assert!(extract(doc, range).is_empty());
+        let range = Range::new(Position::new(4, 0), Position::new(7, 18));
+        assert!(extract(doc, range).is_empty());
+    }
+
+    #[test]
+    fn test_extract_command_without_depends() {
+        let doc = "commands:\n  build:\n    cmd: |\n      echo 1\n      echo 2";
+        let range = Range::new(Position::new(3, 6), Position::new(3, 12));
+        let actions = extract(doc, range);
+        assert_eq!(
+            actions[0].1,
+            "commands:\n  build:\n    depends: [build-extracted]\n    cmd: |\n      echo 2\n  build-extracted:\n    cmd: |\n      echo 1"
+        );
+
+        // lines after the first one can not run before the script
+        let range = Range::new(Position::new(4, 6), Position::new(4, 12));
+        let actions = extract(doc, range);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(
+            actions[0].0,
+            "Extract into new command `build-extracted` called with `lets`"
+        );
+    }
+
+    #[test]
+    fn test_sort_commands_actions() {
+        let doc = r#"
+shell: bash
+commands:
+  # test everything
+  test:
+    cmd: echo Test
+
+  build:
+    description: Build
+    cmd: |
+      go build
+  # aligned with commands
+
+  # lint code
+  lint:
+    description: Lint
+    cmd: echo Lint
+"#
+        .trim_start();
+
+        let uri: Uri = "file:///project/lets.yaml".parse().unwrap();
+        let actions: Vec<(String, String)> = sort_commands_actions(doc, &uri)
+            .into_iter()
+            .map(|action| {
+                let CodeActionOrCommand::CodeAction(action) = action else {
+                    panic!("expected code action");
+                };
+                let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+                (action.title, apply_edits(doc, edits))
+            })
+            .collect();
+        // commands with descriptions are first already when sorted by name
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].0, "Sort commands");
+        assert_eq!(
+            actions[0].1,
+            r#"
+shell: bash
+commands:
+  build:
+    description: Build
+    cmd: |
+      go build
+
+  # aligned with commands
+
+  # lint code
+  lint:
+    description: Lint
+    cmd: echo Lint
+
+  # test everything
+  test:
+    cmd: echo Test
+"#
+            .trim_start()
+        );
+
+        // already sorted
+        let doc = "commands:\n  a:\n    cmd: echo\n  b:\n    cmd: echo\n";
+        assert!(sort_commands_actions(doc, &uri).is_empty());
+
+        // descriptions first gives the same order or there are no descriptions
+        let titles = |doc: &str| -> Vec<String> {
+            sort_commands_actions(doc, &uri)
+                .into_iter()
+                .map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => action.title,
+                    CodeActionOrCommand::Command(command) => command.title,
+                })
+                .collect()
+        };
+        assert_eq!(
+            titles("commands:\n  b:\n    cmd: echo\n  a:\n    cmd: echo\n"),
+            vec!["Sort commands"]
+        );
+        assert_eq!(
+            titles("commands:\n  b:\n    description: B\n  a:\n    description: A\n"),
+            vec!["Sort commands"]
+        );
+        // already sorted by name, descriptions first still moves commands
+        let doc = "commands:\n  # first\n  a:\n    cmd: echo\n  b:\n    description: B\n";
+        assert_eq!(titles(doc), vec!["Sort commands, with descriptions first"]);
+        let CodeActionOrCommand::CodeAction(action) = &sort_commands_actions(doc, &uri)[0] else {
+            panic!("expected code action");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            apply_edits(doc, edits),
+            "commands:\n  b:\n    description: B\n  # first\n  a:\n    cmd: echo\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_commands_keeps_crlf() {
+        let doc = "commands:\r\n  b:\r\n    cmd: echo B\r\n\r\n  a:\r\n    cmd: echo A\r\n";
+        let uri: Uri = "file:///project/lets.yaml".parse().unwrap();
+        let actions = sort_commands_actions(doc, &uri);
+        let Some(CodeActionOrCommand::CodeAction(action)) = actions.first() else {
+            panic!("expected code action");
+        };
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            apply_edits(doc, edits),
+            "commands:\r\n  a:\r\n    cmd: echo A\r\n\r\n  b:\r\n    cmd: echo B\r\n"
+        );
+    }
+
+    #[test]
+    fn test_new_command_edit() {
+        let doc = r#"
+shell: bash
+commands:
+    test:
+        depends: [build]
+        cmd: echo Test
+
+    lint:
+        cmd: |
+            echo Lint
+"#
+        .trim_start();
+
+        let body = [(0, "description: TODO"), (0, "cmd: echo \"TODO\"")];
+        let edit = new_command_edit(&Parser::new(), doc, "build", &body).unwrap();
+        assert_eq!(
+            apply_edit(doc, &edit),
+            r#"
+shell: bash
+commands:
+    test:
+        depends: [build]
+        cmd: echo Test
+
+    lint:
+        cmd: |
+            echo Lint
+
+    build:
+        description: TODO
+        cmd: echo "TODO"
+"#
+            .trim_start()
+        );
+    }
+
+    #[test]
+    fn test_create_command_actions() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    depends: [build, lint]
+    cmd: echo Test"#
+            .trim();
+
+        let uri: Uri = "file:///project/lets.yaml".parse().unwrap();
+        let known = vec!["test".to_string(), "lint".to_string()];
+        let diagnostic = Diagnostic {
+            range: Range::new(Position::new(3, 14), Position::new(3, 19)),
+            code: Some(NumberOrString::String(UNDEFINED_DEPENDENCY.to_string())),
+            message: "Command `build` is not defined".to_string(),
+            ..Default::default()
+        };
+        let range = Range::new(Position::new(3, 15), Position::new(3, 15));
+
+        let actions =
+            create_command_actions(doc, &uri, &range, &known, std::slice::from_ref(&diagnostic));
+        assert_eq!(actions.len(), 1);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected code action");
+        };
+        assert_eq!(action.title, "Create command `build`");
+        assert_eq!(action.diagnostics, Some(vec![diagnostic]));
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits[0].new_text,
+            "\n  build:\n    description: TODO\n    cmd: echo \"TODO\""
+        );
+
+        // cursor on the existing command
+        let range = Range::new(Position::new(3, 22), Position::new(3, 22));
+        assert!(create_command_actions(doc, &uri, &range, &known, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_did_you_mean_actions() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    depends: [biuld]
+    cmd: echo Test
+  release:
+    ref: tset"#
+            .trim();
+
+        let uri: Uri = "file:///project/lets.yaml".parse().unwrap();
+        let known: Vec<String> = ["test", "build", "bulid", "biulds"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let range = Range::new(Position::new(3, 15), Position::new(3, 15));
+
+        let actions: Vec<(String, Option<bool>, String)> =
+            did_you_mean_actions(doc, &uri, &range, &known, &[])
+                .into_iter()
+                .map(|action| {
+                    let CodeActionOrCommand::CodeAction(action) = action else {
+                        panic!("expected code action");
+                    };
+                    let edit = &action.edit.unwrap().changes.unwrap()[&uri][0];
+                    (action.title, action.is_preferred, edit.new_text.clone())
+                })
+                .collect();
+        let expected = [("biulds", true), ("build", false), ("bulid", false)];
+        assert_eq!(actions.len(), expected.len());
+        for ((title, preferred, text), (name, is_preferred)) in actions.iter().zip(expected) {
+            assert_eq!(title, &format!("Change to `{name}`"));
+            assert_eq!(preferred, &Some(is_preferred));
+            assert_eq!(text, name);
+        }
+
+        let range = Range::new(Position::new(6, 10), Position::new(6, 10));
+        assert_eq!(
+            did_you_mean_actions(doc, &uri, &range, &known, &[]).len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+use tree_sitter::Point;
+
+use crate::graph::DependencyGraph;
+use crate::shell::Script;
+use crate::treesitter::{Parser, ReferenceKind};
+
+pub const SOURCE: &str = "lets_ls";
+pub const UNDEFINED_DEPENDENCY: &str = "undefined-dependency";
+pub const DEPENDENCY_CYCLE: &str = "dependency-cycle";
+// Replaced in linter args with the dialect of the config `shell`.
+pub const SHELL_PLACEHOLDER: &str = "{shell}";
+const LINTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// External program that lints shell scripts, e.g. `shellcheck`.
+// The script is passed via stdin and findings are expected on stdout
+// in `shellcheck -f json` format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalLinter {
+    pub path: String,
+    pub args: Vec<String>,
+    // the linter is killed if it does not finish in time
+    pub timeout: Duration,
+}
+
+impl ExternalLinter {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            args: ["-f", "json", "-s", SHELL_PLACEHOLDER, "-"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect(),
+            timeout: LINTER_TIMEOUT,
+        }
+    }
+
+    // Linter for scripts of the config with the given `shell`.
+    pub fn for_shell(&self, shell: Option<&str>) -> Self {
+        let dialect = shell_dialect(shell);
+        Self {
+            args: self
+                .args
+                .iter()
+                .map(|arg| arg.replace(SHELL_PLACEHOLDER, dialect))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    // Read linter from initialization options:
+    // { "linter": { "path": "shellcheck", "args": ["-f", "json", "-"] } }
+    pub fn from_options(options: &serde_json::Value) -> Option<Self> {
+        let linter = options.get("linter")?;
+        let mut result = Self::new(linter.get("path")?.as_str()?);
+        if let Some(args) = linter.get("args").and_then(|args| args.as_array()) {
+            result.args = args
+                .iter()
+                .filter_map(|arg| arg.as_str().map(|arg| arg.to_string()))
+                .collect();
+        }
+        Some(result)
+    }
+
+    fn source(&self) -> String {
+        std::path::Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
+            .to_string()
+    }
+
+    fn run(&self, script: &str) -> anyhow::Result<String> {
+        let mut child = Command::new(&self.path)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // stdin and stdout are served by their own threads,
+        // so a linter printing before it reads the whole script does not block
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("linter stdin is not available"))?;
+        let script = script.to_string();
+        std::thread::spawn(move || stdin.write_all(script.as_bytes()));
+        let mut stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("linter stdout is not available"))?;
+        let reader = std::thread::spawn(move || {
+            let mut output = String::new();
+            stdout.read_to_string(&mut output).map(|_| output)
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        while child.try_wait()?.is_none() {
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                anyhow::bail!("linter did not finish in {:?}", self.timeout);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // linters exit with non-zero code when they find something, so ignore the status
+        let output = reader
+            .join()
+            .map_err(|_| anyhow::anyhow!("linter output can not be read"))??;
+        Ok(output)
+    }
+}
+
+// Dialect of `shellcheck -s` for the config `shell`, e.g. `/bin/sh` -> `sh`.
+// Shells unknown to shellcheck are linted as bash.
+fn shell_dialect(shell: Option<&str>) -> &str {
+    let name = shell
+        .and_then(|shell| shell.split_whitespace().next())
+        .and_then(|shell| shell.rsplit('/').next())
+        .unwrap_or("bash");
+    match name {
+        "sh" | "bash" | "dash" | "ksh" | "busybox" => name,
+        _ => "bash",
+    }
+}
+
+// Diagnostics published for a document: undefined commands, dependency cycles
+// and findings of the linter if any.
+// graph: commands of the config the document belongs to, including mixins
+pub fn document_diagnostics(
+    doc: &str,
+    graph: &DependencyGraph,
+    complete: bool,
+    linter: Option<&ExternalLinter>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = check_dependencies(doc, &graph.commands(), complete);
+    diagnostics.extend(check_cycles(doc, graph));
+    if let Some(linter) = linter {
+        diagnostics.extend(lint_scripts(linter, doc));
+    }
+    diagnostics
+}
+
+// Report `depends` and `ref` items which point to commands that do not exist.
+// known_commands: commands of the config including mixins
+// complete: false if some mixins can not be read, then unknown commands are only hints
+pub fn check_dependencies(doc: &str, known_commands: &[String], complete: bool) -> Vec<Diagnostic> {
+    let severity = if complete {
+        DiagnosticSeverity::ERROR
+    } else {
+        DiagnosticSeverity::HINT
+    };
+    Parser::new()
+        .get_command_references(doc)
+        .into_iter()
+        .filter(|reference| !known_commands.contains(&reference.name))
+        .map(|reference| {
+            let key = match reference.kind {
+                ReferenceKind::Depends => "depends",
+                ReferenceKind::Ref => "ref",
+            };
+            let hint = suggest_commands(&reference.name, known_commands)
+                .first()
+                .map(|suggestion| format!(". Did you mean `{suggestion}`?"))
+                .unwrap_or_default();
+            Diagnostic {
+                range: reference.range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(UNDEFINED_DEPENDENCY.to_string())),
+                source: Some(SOURCE.to_string()),
+                message: format!(
+                    "Command `{}` used in `{}` of `{}` is not defined{}",
+                    reference.name, key, reference.command, hint
+                ),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+// Report `depends` items which lead back to the command, lets can not run such commands.
+pub fn check_cycles(doc: &str, graph: &DependencyGraph) -> Vec<Diagnostic> {
+    Parser::new()
+        .get_command_references(doc)
+        .into_iter()
+        .filter(|reference| reference.kind == ReferenceKind::Depends)
+        .filter_map(|reference| {
+            let cycle = graph.depends_cycle(&reference.command, &reference.name)?;
+            Some(Diagnostic {
+                range: reference.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(DEPENDENCY_CYCLE.to_string())),
+                source: Some(SOURCE.to_string()),
+                message: format!(
+                    "Command `{}` in `depends` of `{}` makes a dependency cycle: {}",
+                    reference.name,
+                    reference.command,
+                    cycle.join(" -> ")
+                ),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+// Return up to 3 known commands similar to the name, the closest goes first.
+pub fn suggest_commands(name: &str, known_commands: &[String]) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut candidates: Vec<(usize, &String)> = known_commands
+        .iter()
+        .map(|command| (edit_distance(name, command), command))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+        .into_iter()
+        .take(3)
+        .map(|(_, command)| command.clone())
+        .collect()
+}
+
+// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            current.push(substitution.min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+// Run the linter over `cmd` script of each command and map findings to document ranges.
+// Scripts are linted in parallel, a batch per available CPU at a time.
+pub fn lint_scripts(linter: &ExternalLinter, doc: &str) -> Vec<Diagnostic> {
+    let scripts: Vec<Script> = Parser::new()
+        .get_scripts(doc)
+        .into_iter()
+        .filter(|script| script.key == "cmd")
+        .collect();
+    let batch = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let mut diagnostics = vec![];
+    for scripts in scripts.chunks(batch) {
+        let outputs: Vec<anyhow::Result<String>> = std::thread::scope(|scope| {
+            let runs: Vec<_> = scripts
+                .iter()
+                .map(|script| scope.spawn(|| linter.run(&script.text)))
+                .collect();
+            runs.into_iter()
+                .map(|run| {
+                    run.join()
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("linter thread panicked")))
+                })
+                .collect()
+        });
+        for (script, output) in scripts.iter().zip(outputs) {
+            match output {
+                Ok(output) => diagnostics.extend(parse_linter_output(linter, script, &output)),
+                Err(err) => log::warn!("failed to run linter {}: {}", linter.path, err),
+            }
+        }
+    }
+    diagnostics
+}
+
+// Convert shellcheck json findings (1-based lines and columns) into diagnostics.
+fn parse_linter_output(linter: &ExternalLinter, script: &Script, output: &str) -> Vec<Diagnostic> {
+    let Ok(serde_json::Value::Array(findings)) = serde_json::from_str(output) else {
+        return vec![];
+    };
+
+    let point = |line: &serde_json::Value, column: &serde_json::Value| -> Option<Point> {
+        let line = line.as_u64()?.checked_sub(1)?;
+        let column = column.as_u64()?.checked_sub(1)?;
+        Some(Point::new(line as usize, column as usize))
+    };
+
+    findings
+        .iter()
+        .filter_map(|finding| {
+            let start = point(&finding["line"], &finding["column"])?;
+            let end = point(&finding["endLine"], &finding["endColumn"]).unwrap_or(start);
+            let severity = match finding["level"].as_str() {
+                Some("error") => DiagnosticSeverity::ERROR,
+                Some("warning") => DiagnosticSeverity::WARNING,
+                Some("info") => DiagnosticSeverity::INFORMATION,
+                _ => DiagnosticSeverity::HINT,
+            };
+            Some(Diagnostic {
+                range: lsp_types::Range::new(
+                    script.to_document_position(start),
+                    script.to_document_position(end),
+                ),
+                severity: Some(severity),
+                code: finding["code"]
+                    .as_u64()
+                    .map(|code| NumberOrString::String(format!("SC{code}"))),
+                source: Some(linter.source()),
+                message: finding["message"].as_str()?.to_string(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create linter script reporting unquoted `$FOO` on the second script line.
+    fn fake_linter(dir: &tempfile::TempDir) -> ExternalLinter {
+        let path = dir.path().join("fake-linter");
+        std::fs::write(
+            &path,
+            r#"#!/bin/sh
+cat > /dev/null
+echo '[{"line":2,"column":6,"endLine":2,"endColumn":10,"level":"info","code":2086,"message":"Double quote to prevent globbing and word splitting."}]'
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        ExternalLinter::new(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_check_dependencies() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    depends: [build, lint]
+    cmd: echo Test
+  release:
+    ref: deploy"#
+            .trim();
+
+        let known = vec!["test".to_string(), "build".to_string()];
+        let diagnostics: Vec<(Position, String)> = check_dependencies(doc, &known, true)
+            .into_iter()
+            .map(|d| (d.range.start, d.message))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    Position::new(3, 21),
+                    "Command `lint` used in `depends` of `test` is not defined".to_string()
+                ),
+                (
+                    Position::new(6, 9),
+                    "Command `deploy` used in `ref` of `release` is not defined".to_string()
+                ),
+            ]
+        );
+
+        // commands may be defined in mixins which can not be read
+        assert!(check_dependencies(doc, &known, false)
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::HINT)));
+    }
+
+    #[test]
+    fn test_suggest_commands() {
+        let known: Vec<String> = ["build", "build-docs", "test", "lint", "bulid"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(suggest_commands("biuld", &known), vec!["build", "bulid"]);
+        assert_eq!(suggest_commands("tset", &known), vec!["test"]);
+        assert!(suggest_commands("deploy", &known).is_empty());
+
+        let doc = "commands:\n  test:\n    depends: [buld]\n    cmd: echo Test";
+        assert_eq!(
+            check_dependencies(doc, &known, true)[0].message,
+            "Command `buld` used in `depends` of `test` is not defined. Did you mean `build`?"
+        );
+    }
+
+    #[test]
+    fn test_lint_scripts() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    cmd: |
+      echo Test
+      echo $FOO
+    after: echo after"#
+            .trim();
+
+        let dir = tempfile::tempdir().unwrap();
+        let diagnostics = lint_scripts(&fake_linter(&dir), doc);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(5, 11), Position::new(5, 15))
+        );
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("SC2086".to_string()))
+        );
+        assert_eq!(diagnostics[0].source, Some("fake-linter".to_string()));
+    }
+
+    #[test]
+    fn test_missing_linter() {
+        let doc = "commands:\n  test:\n    cmd: echo $FOO";
+        let linter = ExternalLinter::new("/nonexistent/linter");
+        assert!(lint_scripts(&linter, doc).is_empty());
+    }
+
+    #[test]
+    fn test_linter_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("slow-linter");
+        std::fs::write(&path, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let linter = ExternalLinter {
+            timeout: Duration::from_millis(100),
+            ..ExternalLinter::new(path.to_str().unwrap())
+        };
+
+        let started = Instant::now();
+        assert!(linter.run("echo $FOO").is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_linter_reads_large_script() {
+        // linter which prints findings before it reads the script
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eager-linter");
+        std::fs::write(&path, "#!/bin/sh\necho '[]'\ncat > /dev/null\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let linter = ExternalLinter::new(path.to_str().unwrap());
+        let script = "echo $FOO\n".repeat(100_000);
+        assert_eq!(linter.run(&script).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn test_linter_shell_dialect() {
+        let linter = ExternalLinter::new("shellcheck");
+        let args = |shell: Option<&str>| linter.for_shell(shell).args[3].clone();
+        assert_eq!(args(Some("bash")), "bash");
+        assert_eq!(args(Some("/bin/sh")), "sh");
+        assert_eq!(args(Some("/usr/bin/dash -e")), "dash");
+        assert_eq!(args(Some("zsh")), "bash");
+        assert_eq!(args(None), "bash");
+
+        let custom = ExternalLinter {
+            args: vec!["--shell={shell}".to_string(), "-".to_string()],
+            ..linter.clone()
+        };
+        assert_eq!(custom.for_shell(Some("ksh")).args, vec!["--shell=ksh", "-"]);
+    }
+
+    #[test]
+    fn test_linter_from_options() {
+        let options = serde_json::json!({"linter": {"path": "shellcheck"}});
+        assert_eq!(
+            ExternalLinter::from_options(&options),
+            Some(ExternalLinter::new("shellcheck"))
+        );
+
+        let options = serde_json::json!({"linter": {"path": "lint", "args": ["-"]}});
+        assert_eq!(
+            ExternalLinter::from_options(&options).map(|linter| linter.args),
+            Some(vec!["-".to_string()])
+        );
+        assert_eq!(ExternalLinter::from_options(&serde_json::json!({})), None);
+    }
+}
@@ -0,0 +1,173 @@
+use lsp_types::{FoldingRange, FoldingRangeKind};
+use tree_sitter::Node;
+
+use crate::treesitter::{find_mapping_value, find_root_mapping, get_pair_key, last_row, Parser};
+
+fn folding_range(start: usize, end: usize, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line: start as u32,
+        end_line: end as u32,
+        kind,
+        ..Default::default()
+    }
+}
+
+fn push_node(ranges: &mut Vec<FoldingRange>, node: &Node) {
+    let start = node.start_position().row;
+    let end = last_row(node);
+    if end > start {
+        ranges.push(folding_range(start, end, None));
+    }
+}
+
+fn block_mapping_pairs<'t>(node: &Node<'t>) -> Vec<Node<'t>> {
+    let Some(mapping) = node
+        .named_child(0)
+        .filter(|mapping| mapping.kind() == "block_mapping")
+    else {
+        return vec![];
+    };
+    let mut cursor = mapping.walk();
+    mapping
+        .named_children(&mut cursor)
+        .filter(|pair| pair.kind() == "block_mapping_pair")
+        .collect()
+}
+
+// Folding ranges for top level sections, commands, their `options` and scripts,
+// and blocks of comments.
+pub fn folding_ranges(doc: &str) -> Vec<FoldingRange> {
+    let parser = Parser::new();
+    let tree = parser.parse(doc);
+    let mut ranges = vec![];
+
+    if let Some(root) = find_root_mapping(&tree.root_node()) {
+        let mut cursor = root.walk();
+        for pair in root.named_children(&mut cursor) {
+            if pair.kind() == "block_mapping_pair" {
+                push_node(&mut ranges, &pair);
+            }
+        }
+        let commands = find_mapping_value(&root, doc, "commands")
+            .map(|commands| block_mapping_pairs(&commands))
+            .unwrap_or_default();
+        for command in commands {
+            push_node(&mut ranges, &command);
+            let options = command
+                .child_by_field_name("value")
+                .map(|body| block_mapping_pairs(&body))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|pair| get_pair_key(pair, doc) == Some("options"));
+            for pair in options {
+                push_node(&mut ranges, &pair);
+            }
+        }
+    }
+
+    for script in parser.get_scripts(doc) {
+        let (start, end) = (script.range.start.line, script.range.end.line);
+        // block scalar may end at the start of the next line
+        let end = if script.range.end.character == 0 && end > start {
+            end - 1
+        } else {
+            end
+        };
+        if end > start {
+            ranges.push(folding_range(start as usize, end as usize, None));
+        }
+    }
+
+    push_comment_blocks(&mut ranges, &tree.root_node(), doc);
+
+    // editors use one range per start line, keep the outermost one
+    ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+    ranges.dedup_by_key(|range| range.start_line);
+    ranges
+}
+
+// Consecutive lines consisting only of comments.
+fn push_comment_blocks(ranges: &mut Vec<FoldingRange>, root: &Node, doc: &str) {
+    let lines: Vec<&str> = doc.lines().collect();
+    let mut rows = vec![];
+    collect_comment_rows(root, &lines, &mut rows);
+    rows.sort();
+
+    let mut block: Option<(usize, usize)> = None;
+    for row in rows {
+        block = match block {
+            Some((start, end)) if row == end + 1 => Some((start, row)),
+            Some((start, end)) => {
+                if end > start {
+                    ranges.push(folding_range(start, end, Some(FoldingRangeKind::Comment)));
+                }
+                Some((row, row))
+            }
+            None => Some((row, row)),
+        };
+    }
+    if let Some((start, end)) = block.filter(|(start, end)| end > start) {
+        ranges.push(folding_range(start, end, Some(FoldingRangeKind::Comment)));
+    }
+}
+
+fn collect_comment_rows(node: &Node, lines: &[&str], rows: &mut Vec<usize>) {
+    if node.kind() == "comment" {
+        let position = node.start_position();
+        let own_line = lines
+            .get(position.row)
+            .is_some_and(|line| line[..position.column].trim().is_empty());
+        if own_line {
+            rows.push(position.row);
+        }
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_comment_rows(&child, lines, rows);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folding_ranges() {
+        let doc = r#"
+shell: bash
+# global env
+# used by all commands
+env:
+  A: 1
+  B: 2
+commands:
+  test:
+    options: |
+      Usage: lets test [<name>]
+    cmd: |
+      echo Test
+      echo Done
+  lint:
+    cmd: echo Lint
+"#
+        .trim_start();
+
+        let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> = folding_ranges(doc)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (1, 2, Some(FoldingRangeKind::Comment)),
+                (3, 5, None),
+                (6, 14, None),
+                (7, 12, None),
+                (8, 9, None),
+                (10, 12, None),
+                (13, 14, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,919 @@
+use lsp_types::{Position, Range, TextEdit};
+use tree_sitter::Node;
+
+use crate::treesitter::{
+    block_scalar_indent, get_node_text, get_pair_key, get_scalar_value, last_row, strip_indent,
+    Parser,
+};
+
+// Default order of keys within a command.
+pub const DEFAULT_KEY_ORDER: [&str; 7] = [
+    "description",
+    "depends",
+    "options",
+    "env",
+    "checksum",
+    "cmd",
+    "after",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    // keys of a command in canonical order, other keys keep their places
+    pub key_order: Vec<String>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            key_order: DEFAULT_KEY_ORDER
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl FormatOptions {
+    // Read options from initialization options:
+    // { "format": { "keyOrder": ["description", "cmd"] } }
+    pub fn from_options(options: &serde_json::Value) -> Self {
+        let mut result = Self::default();
+        if let Some(order) = options
+            .pointer("/format/keyOrder")
+            .and_then(|order| order.as_array())
+        {
+            result.key_order = order
+                .iter()
+                .filter_map(|key| key.as_str().map(|key| key.to_string()))
+                .collect();
+        }
+        result
+    }
+}
+
+// Where a block mapping is located in the config, defines how its keys are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Root,
+    // `commands` mapping: one blank line between commands
+    Commands,
+    // body of a command: keys are sorted, no blank lines
+    CommandBody,
+    Other,
+}
+
+impl Context {
+    fn child(self, key: &str) -> Self {
+        match (self, key) {
+            (Context::Root, "commands") => Context::Commands,
+            (Context::Commands, _) => Context::CommandBody,
+            _ => Context::Other,
+        }
+    }
+}
+
+// Pair or sequence item with comments which belong to it.
+struct Entry<'t> {
+    // comments on their own lines before the node
+    leading: Vec<Node<'t>>,
+    node: Node<'t>,
+    // comment on the last line of the node
+    trailing: Option<Node<'t>>,
+    blank_before: bool,
+}
+
+impl Entry<'_> {
+    fn first_row(&self) -> usize {
+        self.leading
+            .first()
+            .unwrap_or(&self.node)
+            .start_position()
+            .row
+    }
+
+    fn last_row(&self) -> usize {
+        last_row(self.trailing.as_ref().unwrap_or(&self.node))
+    }
+}
+
+struct Formatter<'a> {
+    doc: &'a str,
+    lines: Vec<&'a str>,
+    // line ending of the document, CRLF is kept when the document uses it
+    newline: &'static str,
+    key_order: &'a [String],
+    width: usize,
+    out: Vec<String>,
+    // original column and new indent of enclosing collections, used to place comments
+    levels: Vec<(usize, usize)>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(doc: &'a str, options: &'a FormatOptions, width: usize) -> Self {
+        Self {
+            doc,
+            lines: doc.lines().collect(),
+            newline: if doc.contains("\r\n") { "\r\n" } else { "\n" },
+            key_order: &options.key_order,
+            width: width.max(2),
+            out: vec![],
+            levels: vec![(0, 0)],
+        }
+    }
+
+    fn text(&self, node: &Node) -> Option<&'a str> {
+        get_node_text(node, self.doc)
+    }
+
+    fn has_blank_line(&self, after: Option<usize>, before: usize) -> bool {
+        after.is_some_and(|after| {
+            (after + 1..before).any(|row| self.lines.get(row).is_some_and(|l| l.trim().is_empty()))
+        })
+    }
+
+    // Group items of the collection with their comments, return the entries and comments
+    // left after the last entry.
+    fn entries<'t>(&self, collection: &Node<'t>) -> (Vec<Entry<'t>>, Vec<Node<'t>>, bool) {
+        let mut entries: Vec<Entry> = vec![];
+        let mut pending = vec![];
+        let mut pending_blank = false;
+        let mut prev_row = None;
+        let mut cursor = collection.walk();
+        for child in collection.named_children(&mut cursor) {
+            if child.kind() == "comment" {
+                let row = child.start_position().row;
+                match entries.last_mut() {
+                    Some(last)
+                        if pending.is_empty()
+                            && last.trailing.is_none()
+                            && row == last_row(&last.node) =>
+                    {
+                        last.trailing = Some(child)
+                    }
+                    _ => {
+                        if pending.is_empty() {
+                            pending_blank = self.has_blank_line(prev_row, row);
+                        }
+                        pending.push(child);
+                    }
+                }
+            } else {
+                let blank_before = if pending.is_empty() {
+                    self.has_blank_line(prev_row, child.start_position().row)
+                } else {
+                    pending_blank
+                };
+                entries.push(Entry {
+                    leading: std::mem::take(&mut pending),
+                    node: child,
+                    trailing: None,
+                    blank_before,
+                });
+            }
+            prev_row = Some(last_row(&child));
+        }
+        (entries, pending, pending_blank)
+    }
+
+    // Own line comment is indented like the collection it was aligned with.
+    fn comment(&mut self, comment: &Node) -> Option<()> {
+        let column = comment.start_position().column;
+        let indent = self
+            .levels
+            .iter()
+            .rev()
+            .find(|(original, _)| *original <= column)
+            .map_or(0, |(_, indent)| *indent);
+        let line = format!("{}{}", " ".repeat(indent), self.text(comment)?);
+        self.out.push(line);
+        Some(())
+    }
+
+    fn append(&mut self, text: &str) -> Option<()> {
+        let last = self.out.last_mut()?;
+        last.push(' ');
+        last.push_str(text);
+        Some(())
+    }
+
+    fn entry(&mut self, entry: &Entry, indent: usize, context: Context) -> Option<()> {
+        for comment in &entry.leading {
+            self.comment(comment)?;
+        }
+        match entry.node.kind() {
+            "block_mapping_pair" => self.pair(&entry.node, indent, context)?,
+            "block_sequence_item" => self.item(&entry.node, indent)?,
+            _ => return None,
+        }
+        if let Some(trailing) = &entry.trailing {
+            self.append(self.text(trailing)?)?;
+        }
+        Some(())
+    }
+
+    fn mapping(&mut self, mapping: &Node, indent: usize, context: Context) -> Option<()> {
+        let (mut entries, comments, blank) = self.entries(mapping);
+        if context == Context::CommandBody {
+            entries = self.sort_keys(entries);
+        }
+        self.levels.push((mapping.start_position().column, indent));
+        for (i, entry) in entries.iter().enumerate() {
+            let blank_before = match context {
+                Context::Commands => i > 0,
+                Context::CommandBody => false,
+                _ => entry.blank_before,
+            };
+            if blank_before {
+                self.blank_line(indent);
+            }
+            self.entry(entry, indent, context)?;
+        }
+        self.end_comments(&comments, blank && context != Context::CommandBody)?;
+        self.levels.pop();
+        Some(())
+    }
+
+    // Comments at the end of the previous nested block aligned with the next entry
+    // belong to that entry, so the blank line goes before them.
+    fn blank_line(&mut self, indent: usize) {
+        let mut position = self.out.len();
+        while position > 0 {
+            let line = &self.out[position - 1];
+            let trimmed = line.trim_start();
+            if !trimmed.starts_with('#') || line.len() - trimmed.len() > indent {
+                break;
+            }
+            position -= 1;
+        }
+        self.out.insert(position, String::new());
+    }
+
+    fn sequence(&mut self, sequence: &Node, indent: usize) -> Option<()> {
+        let (entries, comments, blank) = self.entries(sequence);
+        self.levels.push((sequence.start_position().column, indent));
+        for entry in &entries {
+            if entry.blank_before {
+                self.blank_line(indent);
+            }
+            self.entry(entry, indent, Context::Other)?;
+        }
+        self.end_comments(&comments, blank)?;
+        self.levels.pop();
+        Some(())
+    }
+
+    fn end_comments(&mut self, comments: &[Node], blank: bool) -> Option<()> {
+        if blank && !comments.is_empty() {
+            self.out.push(String::new());
+        }
+        for comment in comments {
+            self.comment(comment)?;
+        }
+        Some(())
+    }
+
+    // Put keys from key_order into canonical order, other keys stay where they are.
+    fn sort_keys<'t>(&self, entries: Vec<Entry<'t>>) -> Vec<Entry<'t>> {
+        let rank = |entry: &Entry| {
+            get_pair_key(&entry.node, self.doc)
+                .and_then(|key| self.key_order.iter().position(|k| k == key))
+        };
+        let mut sorted = vec![];
+        let mut slots = vec![];
+        for entry in entries {
+            match rank(&entry) {
+                Some(rank) => {
+                    sorted.push((rank, entry));
+                    slots.push(None);
+                }
+                None => slots.push(Some(entry)),
+            }
+        }
+        sorted.sort_by_key(|(rank, _)| *rank);
+        let mut sorted = sorted.into_iter().map(|(_, entry)| entry);
+        slots
+            .into_iter()
+            .filter_map(|slot| slot.or_else(|| sorted.next()))
+            .collect()
+    }
+
+    fn pair(&mut self, pair: &Node, indent: usize, context: Context) -> Option<()> {
+        let key_node = pair.child_by_field_name("key")?;
+        if key_node.start_position().row != key_node.end_position().row {
+            return None;
+        }
+        let key = self.text(&key_node)?;
+        let head = format!("{}{}:", " ".repeat(indent), key);
+        let value = pair.child_by_field_name("value");
+
+        // comments between the key and a block value
+        let mut cursor = pair.walk();
+        let comments: Vec<Node> = pair
+            .named_children(&mut cursor)
+            .filter(|child| child.kind() == "comment")
+            .collect();
+
+        let Some(value) = value else {
+            self.out.push(head);
+            for comment in &comments {
+                self.comment(comment)?;
+            }
+            return Some(());
+        };
+
+        match value.kind() {
+            "flow_node" => {
+                if !comments.is_empty() {
+                    return None;
+                }
+                self.flow_value(head, &value, pair.start_position().column, indent)
+            }
+            "block_node" => {
+                let child_context = context.child(get_pair_key(pair, self.doc)?);
+                self.block_value(head, &value, indent, child_context, &comments)
+            }
+            _ => None,
+        }
+    }
+
+    fn item(&mut self, item: &Node, indent: usize) -> Option<()> {
+        let pad = " ".repeat(indent);
+        let Some(value) = item.named_child(0) else {
+            self.out.push(format!("{pad}-"));
+            return Some(());
+        };
+        match value.kind() {
+            "flow_node" => self.flow_value(
+                format!("{pad}-"),
+                &value,
+                item.start_position().column,
+                indent,
+            ),
+            "block_node" => {
+                let content = value.named_child(0)?;
+                if value.named_child_count() == 1
+                    && matches!(content.kind(), "block_mapping" | "block_sequence")
+                {
+                    // first line of nested collection goes right after the dash
+                    let start = self.out.len();
+                    let nested = indent + self.width;
+                    match content.kind() {
+                        "block_mapping" => self.mapping(&content, nested, Context::Other)?,
+                        _ => self.sequence(&content, nested)?,
+                    }
+                    let first = self.out.get(start)?.trim_start().to_string();
+                    if first.starts_with('#') {
+                        return None;
+                    }
+                    let padding = " ".repeat(self.width - 1);
+                    self.out[start] = format!("{pad}-{padding}{first}");
+                    Some(())
+                } else {
+                    self.block_value(format!("{pad}-"), &value, indent, Context::Other, &[])
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // head: indented key with colon or a dash of a sequence item
+    // column: original column of the key or dash
+    fn flow_value(
+        &mut self,
+        head: String,
+        value: &Node,
+        column: usize,
+        indent: usize,
+    ) -> Option<()> {
+        let text = self.text(value)?;
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or("");
+        let first = if text.contains('\n') {
+            first.to_string()
+        } else {
+            normalize_quotes(value, text)
+        };
+        self.out.push(format!("{head} {first}"));
+        // continuation lines of multiline scalars and flow collections
+        for line in lines {
+            if line.trim().is_empty() {
+                self.out.push(String::new());
+                continue;
+            }
+            let original = line.len() - line.trim_start().len();
+            let shifted = (original + indent).saturating_sub(column).max(indent + 1);
+            self.out
+                .push(format!("{}{}", " ".repeat(shifted), line.trim_start()));
+        }
+        Some(())
+    }
+
+    fn block_value(
+        &mut self,
+        head: String,
+        value: &Node,
+        indent: usize,
+        context: Context,
+        comments: &[Node],
+    ) -> Option<()> {
+        let mut cursor = value.walk();
+        let children: Vec<Node> = value.named_children(&mut cursor).collect();
+        let (content, properties) = children.split_last()?;
+        let mut line = head;
+        for property in properties {
+            if !matches!(property.kind(), "anchor" | "tag") {
+                return None;
+            }
+            line.push(' ');
+            line.push_str(self.text(property)?);
+        }
+
+        let key_row = value.parent()?.start_position().row;
+        let (same_line, own_line): (Vec<&Node>, Vec<&Node>) = comments
+            .iter()
+            .partition(|comment| comment.start_position().row == key_row);
+
+        let nested = indent + self.width;
+        match content.kind() {
+            "block_scalar" => {
+                if !comments.is_empty() {
+                    return None;
+                }
+                let text = self.text(content)?;
+                let header = text.lines().next()?;
+                // explicit indentation indicator depends on the original layout
+                if header
+                    .split('#')
+                    .next()?
+                    .contains(|c: char| c.is_ascii_digit())
+                {
+                    return None;
+                }
+                self.out.push(format!("{line} {}", header.trim_end()));
+                self.block_scalar_body(content, nested);
+                Some(())
+            }
+            "block_mapping" | "block_sequence" => {
+                self.out.push(line);
+                for comment in same_line {
+                    self.append(self.text(comment)?)?;
+                }
+                self.levels.push((content.start_position().column, nested));
+                for comment in own_line {
+                    self.comment(comment)?;
+                }
+                self.levels.pop();
+                match content.kind() {
+                    "block_mapping" => self.mapping(content, nested, context),
+                    _ => self.sequence(content, nested),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Shift script body to the new indentation keeping relative indentation of its lines.
+    fn block_scalar_body(&mut self, scalar: &Node, indent: usize) {
+        let start = scalar.start_position().row + 1;
+        let end = last_row(scalar);
+        let body: Vec<&str> = (start..=end)
+            .filter_map(|row| self.lines.get(row).copied())
+            .collect();
+        let original = block_scalar_indent(body.iter().copied());
+        for line in body {
+            let content = strip_indent(line, original);
+            if content.is_empty() {
+                self.out.push(String::new());
+            } else {
+                self.out.push(format!("{}{}", " ".repeat(indent), content));
+            }
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut text = self.out.join(self.newline);
+        text.push_str(self.newline);
+        text
+    }
+}
+
+// Use double quotes for single quoted scalars when it does not need escaping.
+fn normalize_quotes(value: &Node, text: &str) -> String {
+    let is_single_quoted = value.named_child_count() == 1
+        && value
+            .named_child(0)
+            .is_some_and(|scalar| scalar.kind() == "single_quote_scalar");
+    if !is_single_quoted || text.len() < 2 {
+        return text.to_string();
+    }
+    let content = text[1..text.len() - 1].replace("''", "'");
+    if content.contains(['"', '\\']) {
+        return text.to_string();
+    }
+    format!("\"{content}\"")
+}
+
+// Return the top level entries of the document together with the root mapping.
+fn root_mapping<'t>(tree: &'t tree_sitter::Tree) -> Option<Node<'t>> {
+    let stream = tree.root_node();
+    if stream.has_error() {
+        return None;
+    }
+    let mut cursor = stream.walk();
+    let documents: Vec<Node> = stream
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() != "comment")
+        .collect();
+    let [document] = documents.as_slice() else {
+        return None;
+    };
+    // `---` markers and directives are not supported
+    let block_node = document.child(0)?;
+    if document.child_count() != 1 || block_node.kind() != "block_node" {
+        return None;
+    }
+    block_node
+        .named_child(0)
+        .filter(|mapping| mapping.kind() == "block_mapping" && block_node.named_child_count() == 1)
+}
+
+fn count_comments(node: &Node) -> usize {
+    let mut cursor = node.walk();
+    let nested: usize = node
+        .children(&mut cursor)
+        .map(|child| count_comments(&child))
+        .sum();
+    nested + usize::from(node.kind() == "comment")
+}
+
+// Values of block scalars, i.e. scripts, in document order.
+fn block_scalars(node: &Node, text: &str, values: &mut Vec<Option<String>>) {
+    if node.kind() == "block_scalar" {
+        values.push(get_scalar_value(node, text));
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        block_scalars(&child, text, values);
+    }
+}
+
+// Make sure formatting did not break the config: comments and scripts are kept as is.
+fn is_valid(parser: &Parser, doc: &str, original: &tree_sitter::Tree, text: &str) -> bool {
+    let tree = parser.parse(text);
+    if tree.root_node().has_error()
+        || count_comments(&tree.root_node()) != count_comments(&original.root_node())
+    {
+        return false;
+    }
+    let (mut before, mut after) = (vec![], vec![]);
+    block_scalars(&original.root_node(), doc, &mut before);
+    block_scalars(&tree.root_node(), text, &mut after);
+    before == after
+}
+
+fn document_end(doc: &str) -> Position {
+    let line = doc.matches('\n').count();
+    let character = doc.rsplit('\n').next().unwrap_or("").len();
+    Position::new(line as u32, character as u32)
+}
+
+// Format the whole document, return an empty list if it is already formatted
+// or can not be formatted safely.
+pub fn format_document(doc: &str, options: &FormatOptions, width: usize) -> Vec<TextEdit> {
+    let parser = Parser::new();
+    let tree = parser.parse(doc);
+    let Some(mapping) = root_mapping(&tree) else {
+        return vec![];
+    };
+
+    let mut formatter = Formatter::new(doc, options, width);
+    let stream = tree.root_node();
+    let mut cursor = stream.walk();
+    let comments: Vec<Node> = stream
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() == "comment")
+        .collect();
+    let (before, after): (Vec<Node>, Vec<Node>) = comments
+        .into_iter()
+        .partition(|comment| comment.start_byte() < mapping.start_byte());
+
+    let mut ok = true;
+    for comment in &before {
+        ok &= formatter.comment(comment).is_some();
+    }
+    if formatter.has_blank_line(before.last().map(last_row), mapping.start_position().row) {
+        formatter.out.push(String::new());
+    }
+    ok &= formatter.mapping(&mapping, 0, Context::Root).is_some();
+    for comment in &after {
+        ok &= formatter.comment(comment).is_some();
+    }
+
+    let text = formatter.finish();
+    if !ok || text == doc || !is_valid(&parser, doc, &tree, &text) {
+        return vec![];
+    }
+    vec![TextEdit {
+        range: Range::new(Position::new(0, 0), document_end(doc)),
+        new_text: text,
+    }]
+}
+
+// Format top level keys and commands which intersect the range.
+// Each of them keeps its indentation so the rest of the document stays valid.
+pub fn format_range(
+    doc: &str,
+    range: &Range,
+    options: &FormatOptions,
+    width: usize,
+) -> Vec<TextEdit> {
+    let parser = Parser::new();
+    let tree = parser.parse(doc);
+    let Some(mapping) = root_mapping(&tree) else {
+        return vec![];
+    };
+
+    let formatter = Formatter::new(doc, options, width);
+    let overlaps = |entry: &Entry| {
+        entry.first_row() <= range.end.line as usize
+            && range.start.line as usize <= entry.last_row()
+    };
+    let covers = |entry: &Entry| {
+        range.start.line as usize <= entry.first_row()
+            && entry.last_row() <= range.end.line as usize
+    };
+
+    // (entry, context of the mapping the entry belongs to)
+    let mut targets = vec![];
+    let (entries, _, _) = formatter.entries(&mapping);
+    for entry in entries.into_iter().filter(|entry| overlaps(entry)) {
+        let commands = (get_pair_key(&entry.node, doc) == Some("commands") && !covers(&entry))
+            .then(|| entry.node.child_by_field_name("value")?.named_child(0))
+            .flatten()
+            .filter(|commands| commands.kind() == "block_mapping");
+        match commands {
+            Some(commands) => {
+                let (commands, _, _) = formatter.entries(&commands);
+                targets.extend(
+                    commands
+                        .into_iter()
+                        .filter(|entry| overlaps(entry))
+                        .map(|entry| (entry, Context::Commands)),
+                );
+            }
+            None => targets.push((entry, Context::Root)),
+        }
+    }
+
+    let mut edits = vec![];
+    for (entry, context) in targets {
+        let mut formatter = Formatter::new(doc, options, width);
+        let column = entry.node.start_position().column;
+        formatter.levels = vec![(column, column)];
+        if formatter.entry(&entry, column, context).is_none() {
+            return vec![];
+        }
+        let first = entry.first_row();
+        let last = entry.last_row();
+        let line_count = formatter.lines.len();
+        let original = formatter.lines[first..=last].join(formatter.newline) + formatter.newline;
+        let text = formatter.finish();
+        if text == original {
+            continue;
+        }
+        let end = if last + 1 < line_count {
+            Position::new(last as u32 + 1, 0)
+        } else {
+            document_end(doc)
+        };
+        edits.push(TextEdit {
+            range: Range::new(Position::new(first as u32, 0), end),
+            new_text: text,
+        });
+    }
+    if !edits.is_empty() && !is_valid(&parser, doc, &tree, &apply_edits(doc, &edits)) {
+        return vec![];
+    }
+    edits
+}
+
+// Apply edits which go in document order and do not overlap.
+fn apply_edits(doc: &str, edits: &[TextEdit]) -> String {
+    let offset = |position: &Position| {
+        doc.split_inclusive('\n')
+            .take(position.line as usize)
+            .map(str::len)
+            .sum::<usize>()
+            + position.character as usize
+    };
+    let mut result = doc.to_string();
+    for edit in edits.iter().rev() {
+        let range = offset(&edit.range.start)..offset(&edit.range.end);
+        result.replace_range(range, &edit.new_text);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(doc: &str) -> String {
+        match format_document(doc, &FormatOptions::default(), 2).first() {
+            Some(edit) => edit.new_text.clone(),
+            None => doc.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_document() {
+        let doc = r#"
+# lets config
+shell:   bash
+mixins: [lets.build.yaml] # mixins
+
+
+env:
+    NAME: 'value'
+    QUOTED: 'it''s'
+commands:
+    # runs tests
+    test:
+        cmd: |
+            go test ./...
+              -v
+        # why
+        depends:
+          - build
+          - lint # lint first
+        description: Run tests
+    build:
+        cmd: [go, build]
+    lint:
+        work_dir: src
+        cmd: echo 'lint \ me'
+        description: Lint
+"#
+        .trim_start();
+
+        assert_eq!(
+            format(doc),
+            r#"
+# lets config
+shell: bash
+mixins: [lets.build.yaml] # mixins
+
+env:
+  NAME: "value"
+  QUOTED: "it's"
+commands:
+  # runs tests
+  test:
+    description: Run tests
+    # why
+    depends:
+      - build
+      - lint # lint first
+    cmd: |
+      go test ./...
+        -v
+
+  build:
+    cmd: [go, build]
+
+  lint:
+    work_dir: src
+    description: Lint
+    cmd: echo 'lint \ me'
+"#
+            .trim_start()
+        );
+    }
+
+    #[test]
+    fn test_format_formatted_document() {
+        let doc = "shell: bash\n\ncommands:\n  test:\n    cmd: echo\n\n  lint:\n    cmd: echo\n";
+        assert!(format_document(doc, &FormatOptions::default(), 2).is_empty());
+
+        // invalid yaml is not formatted
+        let doc = "commands:\n  test: [\n";
+        assert!(format_document(doc, &FormatOptions::default(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_format_range() {
+        let doc = r#"
+shell:   bash
+commands:
+    test:
+        cmd: echo Test
+        description: 'Test'
+    lint:
+        cmd: echo Lint
+        description: Lint
+"#
+        .trim_start();
+
+        let range = Range::new(Position::new(3, 0), Position::new(3, 5));
+        let edits = format_range(doc, &range, &FormatOptions::default(), 2);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 0), Position::new(5, 0))
+        );
+        assert_eq!(
+            edits[0].new_text,
+            "    test:\n      description: \"Test\"\n      cmd: echo Test\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_scripts() {
+        // tab after the indentation is a part of the script
+        let doc = "commands:\n    test:\n        cmd: |\n          \tfoo\n            bar\n";
+        assert_eq!(
+            format(doc),
+            "commands:\n  test:\n    cmd: |\n      \tfoo\n        bar\n"
+        );
+
+        // non-ASCII whitespace is not indentation either
+        let doc = "commands:\n    test:\n        cmd: |\n          \u{3000}y\n            x\n";
+        assert_eq!(
+            format(doc),
+            "commands:\n  test:\n    cmd: |\n      \u{3000}y\n        x\n"
+        );
+        let range = Range::new(Position::new(1, 0), Position::new(2, 0));
+        let edits = format_range(doc, &range, &FormatOptions::default(), 2);
+        assert_eq!(
+            apply_edits(doc, &edits),
+            "commands:\n    test:\n      cmd: |\n        \u{3000}y\n          x\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_crlf() {
+        let doc = "shell: bash\r\n# build it\r\ncommands:\r\n    build:\r\n        cmd: |\r\n          echo\r\n";
+        let expected =
+            "shell: bash\r\n# build it\r\ncommands:\r\n  build:\r\n    cmd: |\r\n      echo\r\n";
+        assert_eq!(format(doc), expected);
+        assert_eq!(format(expected), expected);
+
+        let range = Range::new(Position::new(3, 0), Position::new(4, 0));
+        let edits = format_range(doc, &range, &FormatOptions::default(), 2);
+        assert_eq!(
+            apply_edits(doc, &edits),
+            "shell: bash\r\n# build it\r\ncommands:\r\n    build:\r\n      cmd: |\r\n        echo\r\n"
+        );
+    }
+
+    #[test]
+    fn test_format_range_with_comments() {
+        let doc = r#"
+commands:
+    # run tests
+    test:
+        # command
+        cmd: echo Test # inline
+        description: 'Test'
+    lint:
+        cmd: echo Lint
+"#
+        .trim_start();
+
+        let range = Range::new(Position::new(3, 0), Position::new(4, 0));
+        let edits = format_range(doc, &range, &FormatOptions::default(), 2);
+        assert_eq!(
+            apply_edits(doc, &edits),
+            r#"
+commands:
+    # run tests
+    test:
+      # command
+      description: "Test"
+      cmd: echo Test # inline
+    lint:
+        cmd: echo Lint
+"#
+            .trim_start()
+        );
+
+        // comment between the key and the script can not be kept
+        let doc = "commands:\n    test:\n        cmd: # note\n          echo\n";
+        let range = Range::new(Position::new(1, 0), Position::new(2, 0));
+        assert!(format_range(doc, &range, &FormatOptions::default(), 2).is_empty());
+    }
+
+    #[test]
+    fn test_format_options() {
+        let options = serde_json::json!({"format": {"keyOrder": ["cmd", "description"]}});
+        let options = FormatOptions::from_options(&options);
+        assert_eq!(options.key_order, vec!["cmd", "description"]);
+
+        let doc = "commands:\n  test:\n    description: Test\n    cmd: echo\n";
+        assert_eq!(
+            format_document(doc, &options, 2)[0].new_text,
+            "commands:\n  test:\n    cmd: echo\n    description: Test\n"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+
+use crate::state::uri_to_path;
+use crate::treesitter::{Parser, ReferenceKind};
+
+pub const GRAPH_COMMAND: &str = "lets.dependencyGraph";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct GraphNode {
+    pub name: String,
+    // file the command is defined in, relative to the config directory,
+    // None if the command is not defined anywhere
+    pub file: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: ReferenceKind,
+}
+
+// Commands of a config and its mixins connected by `depends` and `ref`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+// Path of the document relative to the directory of the root config.
+fn relative_file(root: &str, uri: &str) -> String {
+    let (Some(root), Some(path)) = (uri_to_path(root), uri_to_path(uri)) else {
+        return uri.to_string();
+    };
+    root.parent()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .or_else(|| path.file_name().map(std::path::Path::new))
+        .unwrap_or(&path)
+        .display()
+        .to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl DependencyGraph {
+    // Build graph from (uri, text) of the config and its mixins, the config goes first.
+    pub fn from_documents(documents: &[(String, String)]) -> Self {
+        let parser = Parser::new();
+        let mut graph = Self::default();
+        let Some((root, _)) = documents.first() else {
+            return graph;
+        };
+        for (uri, doc) in documents {
+            for command in parser.get_commands(doc) {
+                if graph.find(&command.name).is_none() {
+                    graph.nodes.push(GraphNode {
+                        name: command.name,
+                        file: Some(relative_file(root, uri)),
+                    });
+                }
+            }
+        }
+        for (_, doc) in documents {
+            for reference in parser.get_command_references(doc) {
+                let (Some(from), to) =
+                    (graph.find(&reference.command), graph.find(&reference.name))
+                else {
+                    continue;
+                };
+                let to = to.unwrap_or_else(|| {
+                    graph.nodes.push(GraphNode {
+                        name: reference.name.clone(),
+                        file: None,
+                    });
+                    graph.nodes.len() - 1
+                });
+                let edge = GraphEdge {
+                    from,
+                    to,
+                    kind: reference.kind,
+                };
+                if !graph.edges.contains(&edge) {
+                    graph.edges.push(edge);
+                }
+            }
+        }
+        graph
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    // Names of commands defined in the config and its mixins.
+    pub fn commands(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|node| node.file.is_some())
+            .map(|node| node.name.clone())
+            .collect()
+    }
+
+    // Return the cycle if `from` depends on `to` and `to` leads back to `from` via `depends`,
+    // e.g. [build, migrate, build]. The shortest way back is returned.
+    pub fn depends_cycle(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let from = self.find(from)?;
+        let to = self.find(to)?;
+        let mut previous: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut visited = vec![false; self.nodes.len()];
+        visited[to] = true;
+        let mut queue = VecDeque::from([to]);
+        while let Some(node) = queue.pop_front() {
+            if node == from {
+                let mut path = vec![node];
+                while let Some(node) = previous[*path.last()?] {
+                    path.push(node);
+                }
+                path.push(from);
+                return Some(
+                    path.into_iter()
+                        .rev()
+                        .map(|node| self.nodes[node].name.clone())
+                        .collect(),
+                );
+            }
+            for edge in &self.edges {
+                if edge.from == node && edge.kind == ReferenceKind::Depends && !visited[edge.to] {
+                    visited[edge.to] = true;
+                    previous[edge.to] = Some(node);
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph lets {\n  node [shape = box];\n");
+        for node in &self.nodes {
+            let file = node.file.as_deref().unwrap_or("undefined");
+            let style = if node.file.is_none() {
+                ", style = dashed"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "  \"{}\" [label = \"{}\\n({})\"{}];\n",
+                escape(&node.name),
+                escape(&node.name),
+                escape(file),
+                style
+            ));
+        }
+        for edge in &self.edges {
+            let attrs = match edge.kind {
+                ReferenceKind::Depends => "",
+                ReferenceKind::Ref => " [style = dashed, label = \"ref\"]",
+            };
+            out.push_str(&format!(
+                "  \"{}\" -> \"{}\"{};\n",
+                escape(&self.nodes[edge.from].name),
+                escape(&self.nodes[edge.to].name),
+                attrs
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    // Node ids are generated since command names may contain characters
+    // which are not allowed in mermaid ids.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let file = node.file.as_deref().unwrap_or("undefined");
+            out.push_str(&format!(
+                "  n{}[\"{}<br/>({})\"]\n",
+                i,
+                node.name.replace('"', "#quot;"),
+                file.replace('"', "#quot;")
+            ));
+        }
+        for edge in &self.edges {
+            let arrow = match edge.kind {
+                ReferenceKind::Depends => "-->",
+                ReferenceKind::Ref => "-. ref .->",
+            };
+            out.push_str(&format!("  n{} {} n{}\n", edge.from, arrow, edge.to));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documents() -> Vec<(String, String)> {
+        vec![
+            (
+                "file:///project/lets.yaml".to_string(),
+                r#"
+shell: bash
+mixins:
+  - lets/db.yaml
+commands:
+  build:
+    depends: [migrate]
+    cmd: echo build
+  release:
+    ref: build
+    args: --release
+"#
+                .to_string(),
+            ),
+            (
+                "file:///project/lets/db.yaml".to_string(),
+                r#"
+commands:
+  migrate:
+    depends: [build, missing]
+    cmd: echo migrate
+"#
+                .to_string(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_dependency_graph_dot() {
+        let graph = DependencyGraph::from_documents(&documents());
+        assert_eq!(
+            graph.render(GraphFormat::Dot),
+            r#"digraph lets {
+  node [shape = box];
+  "build" [label = "build\n(lets.yaml)"];
+  "release" [label = "release\n(lets.yaml)"];
+  "migrate" [label = "migrate\n(lets/db.yaml)"];
+  "missing" [label = "missing\n(undefined)", style = dashed];
+  "build" -> "migrate";
+  "release" -> "build" [style = dashed, label = "ref"];
+  "migrate" -> "build";
+  "migrate" -> "missing";
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_depends_cycle() {
+        let graph = DependencyGraph::from_documents(&documents());
+        assert_eq!(graph.commands(), vec!["build", "release", "migrate"]);
+        assert_eq!(
+            graph.depends_cycle("build", "migrate"),
+            Some(vec![
+                "build".to_string(),
+                "migrate".to_string(),
+                "build".to_string()
+            ])
+        );
+        assert_eq!(graph.depends_cycle("migrate", "missing"), None);
+        // `ref` does not run the command before
+        assert_eq!(graph.depends_cycle("release", "build"), None);
+
+        let graph = DependencyGraph::from_documents(&[(
+            "file:///project/lets.yaml".to_string(),
+            "commands:\n  test:\n    depends: [test]\n    cmd: echo".to_string(),
+        )]);
+        assert_eq!(
+            graph.depends_cycle("test", "test"),
+            Some(vec!["test".to_string(), "test".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_dependency_graph_mermaid() {
+        let graph = DependencyGraph::from_documents(&documents());
+        assert_eq!(
+            graph.render(GraphFormat::Mermaid),
+            r#"flowchart LR
+  n0["build<br/>(lets.yaml)"]
+  n1["release<br/>(lets.yaml)"]
+  n2["migrate<br/>(lets/db.yaml)"]
+  n3["missing<br/>(undefined)"]
+  n0 --> n2
+  n1 -. ref .-> n0
+  n2 --> n0
+  n2 --> n3
+"#
+        );
+    }
+}
@@ -1,12 +1,41 @@
-use lsp_server::{Notification, Request, RequestId};
+use clap::ValueEnum;
+use lsp_server::{ErrorCode, Notification, Request, RequestId, Response};
 use lsp_types::CompletionParams;
 use lsp_types::{
-    lsif::DefinitionResultType, request::GotoTypeDefinitionParams, DidChangeTextDocumentParams,
-    DidOpenTextDocumentParams, Location, Range,
+    lsif::DefinitionResultType, request::GotoTypeDefinitionParams, CallHierarchyIncomingCall,
+    CallHierarchyIncomingCallsParams, CallHierarchyItem, CallHierarchyOutgoingCall,
+    CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams, CodeActionOrCommand,
+    CodeActionParams, CodeLens, CodeLensParams, ConfigurationItem, ConfigurationParams, Diagnostic,
+    DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+    DidChangeWatchedFilesRegistrationOptions, DidChangeWorkspaceFoldersParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentFormattingParams,
+    DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams,
+    DocumentRangeFormattingParams, ExecuteCommandParams, FileSystemWatcher, FoldingRange,
+    FoldingRangeParams, GlobPattern, Hover, HoverContents, HoverParams, Location, MarkupContent,
+    MarkupKind, Position, Range, Registration, RegistrationParams, SelectionRange,
+    SelectionRangeParams, SemanticTokens, SemanticTokensParams, SemanticTokensRangeParams,
+    TextEdit,
 };
 
-use crate::state::State;
-use crate::treesitter::{Command, Parser, PositionType};
+use crate::builtins::builtin_env;
+use crate::call_hierarchy::{incoming_calls, outgoing_calls, prepare};
+use crate::code_actions::{
+    convert_sequence_actions, create_command_actions, did_you_mean_actions,
+    extract_command_actions, sort_commands_actions,
+};
+use crate::diagnostics::{document_diagnostics, lint_scripts};
+use crate::folding::folding_ranges;
+use crate::formatting::{format_document, format_range};
+use crate::graph::{DependencyGraph, GraphFormat, GRAPH_COMMAND};
+use crate::logging;
+use crate::runner::{RunRequest, RUN_COMMAND, RUN_WITH_ARGS_COMMAND};
+use crate::selection::selection_ranges;
+use crate::semantic_tokens::semantic_tokens;
+use crate::settings::{self, Settings};
+use crate::state::{mixin_uri, same_file, uri_to_path, LintResult, State, WorkspaceFolder};
+use crate::treesitter::{
+    env_prefix_before_cursor, Command, EnvValue, EnvVar, Parser, PositionType,
+};
 
 #[derive(Debug)]
 pub struct DefinitionResult {
@@ -33,40 +62,420 @@ pub struct CompletionResult {
     pub list: Vec<LSPCompletion>,
 }
 
+#[derive(Debug)]
+pub struct HoverResult {
+    pub id: RequestId,
+    pub value: Hover,
+}
+
+#[derive(Debug)]
+pub struct SemanticTokensResult {
+    pub id: RequestId,
+    pub value: SemanticTokens,
+}
+
+#[derive(Debug)]
+pub struct DiagnosticsResult {
+    pub uri: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug)]
+pub struct CodeLensResult {
+    pub id: RequestId,
+    pub value: Vec<CodeLens>,
+}
+
+#[derive(Debug)]
+pub struct CodeActionResult {
+    pub id: RequestId,
+    pub value: Vec<CodeActionOrCommand>,
+}
+
+#[derive(Debug)]
+pub struct FormattingResult {
+    pub id: RequestId,
+    pub value: Vec<TextEdit>,
+}
+
+#[derive(Debug)]
+pub struct FoldingRangeResult {
+    pub id: RequestId,
+    pub value: Vec<FoldingRange>,
+}
+
+#[derive(Debug)]
+pub struct SelectionRangeResult {
+    pub id: RequestId,
+    pub value: Vec<SelectionRange>,
+}
+
+#[derive(Debug)]
+pub struct DocumentHighlightResult {
+    pub id: RequestId,
+    pub value: Vec<DocumentHighlight>,
+}
+
+#[derive(Debug)]
+pub struct PrepareCallHierarchyResult {
+    pub id: RequestId,
+    pub value: Option<Vec<CallHierarchyItem>>,
+}
+
+#[derive(Debug)]
+pub struct IncomingCallsResult {
+    pub id: RequestId,
+    pub value: Vec<CallHierarchyIncomingCall>,
+}
+
+#[derive(Debug)]
+pub struct OutgoingCallsResult {
+    pub id: RequestId,
+    pub value: Vec<CallHierarchyOutgoingCall>,
+}
+
+#[derive(Debug)]
+pub struct DependencyGraphResult {
+    pub id: RequestId,
+    pub value: String,
+}
+
+// Request sent from the server to the client.
+#[derive(Debug)]
+pub struct ClientRequestResult {
+    pub request: Request,
+}
+
+#[derive(Debug)]
+pub struct RunResult {
+    pub id: RequestId,
+    pub request: RunRequest,
+}
+
+// Request which can not be handled, answered with an error.
+#[derive(Debug)]
+pub struct ErrorResult {
+    pub id: RequestId,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
 #[derive(Debug)]
 pub enum LspResult {
     OK,
     Definition(DefinitionResult),
     Completion(CompletionResult),
+    Hover(HoverResult),
+    SemanticTokens(SemanticTokensResult),
+    Diagnostics(DiagnosticsResult),
+    RefreshDiagnostics(Vec<DiagnosticsResult>),
+    CodeLens(CodeLensResult),
+    CodeAction(CodeActionResult),
+    Formatting(FormattingResult),
+    FoldingRange(FoldingRangeResult),
+    SelectionRange(SelectionRangeResult),
+    DocumentHighlight(DocumentHighlightResult),
+    PrepareCallHierarchy(PrepareCallHierarchyResult),
+    IncomingCalls(IncomingCallsResult),
+    OutgoingCalls(OutgoingCallsResult),
+    DependencyGraph(DependencyGraphResult),
+    ClientRequest(ClientRequestResult),
+    Run(RunResult),
+    Error(ErrorResult),
 }
 
 #[allow(non_snake_case)]
 pub fn handle_didOpen(notf: Notification, state: &mut State) -> Option<LspResult> {
     let params: DidOpenTextDocumentParams = serde_json::from_value(notf.params).ok()?;
-    state.add_document(
-        params.text_document.uri.to_string(),
-        params.text_document.text,
-    );
-    Some(LspResult::OK)
+    let uri = params.text_document.uri.to_string();
+    state.add_document(uri.clone(), params.text_document.text);
+    lint_document(&uri, state);
+    Some(publish_diagnostics(uri, state))
 }
 
 #[allow(non_snake_case)]
 pub fn handle_didChange(notf: Notification, state: &mut State) -> Option<LspResult> {
     let params: DidChangeTextDocumentParams = serde_json::from_value(notf.params).ok()?;
+    let uri = params.text_document.uri.to_string();
     for change in params.content_changes {
-        state.update_document(params.text_document.uri.to_string(), change.text);
+        state.update_document(uri.clone(), change.text);
+    }
+    Some(publish_diagnostics(uri, state))
+}
+
+pub fn handle_did_save(notf: Notification, state: &mut State) -> Option<LspResult> {
+    let params: DidSaveTextDocumentParams = serde_json::from_value(notf.params).ok()?;
+    let uri = params.text_document.uri.to_string();
+    if let Some(text) = params.text {
+        state.update_document(uri.clone(), text);
+    }
+    lint_document(&uri, state);
+    Some(publish_diagnostics(uri, state))
+}
+
+// The external linter is too slow to run on every change, so it runs on open and save
+// and its findings are published along with other diagnostics until the next run.
+fn lint_document(uri: &str, state: &mut State) {
+    let (Some(linter), Some(doc)) = (&state.settings.linter, state.get_document(uri)) else {
+        state.pending_lints.remove(uri);
+        state.lint_diagnostics.insert(uri.to_string(), vec![]);
+        return;
+    };
+    // scripts run with the shell of the root config
+    let shell = state
+        .read_document(&state.find_root_config(uri))
+        .and_then(|root| Parser::new().get_shell(&root));
+    let linter = linter.for_shell(shell.as_deref());
+    let doc = doc.clone();
+    match state.lint_results.clone() {
+        Some(sender) => {
+            let id = state.next_lint_id();
+            state.pending_lints.insert(uri.to_string(), id);
+            let uri = uri.to_string();
+            std::thread::spawn(move || {
+                let diagnostics = lint_scripts(&linter, &doc);
+                let _ = sender.send(LintResult {
+                    uri,
+                    id,
+                    diagnostics,
+                });
+            });
+        }
+        None => {
+            let diagnostics = lint_scripts(&linter, &doc);
+            state.lint_diagnostics.insert(uri.to_string(), diagnostics);
+        }
+    }
+}
+
+// Background lint is done, publish its findings unless the document was linted again meanwhile.
+pub fn handle_lint_finished(result: LintResult, state: &mut State) -> Option<LspResult> {
+    if state.pending_lints.get(&result.uri) != Some(&result.id) {
+        return None;
+    }
+    state.pending_lints.remove(&result.uri);
+    state
+        .lint_diagnostics
+        .insert(result.uri.clone(), result.diagnostics);
+    Some(publish_diagnostics(result.uri, state))
+}
+
+fn publish_diagnostics(uri: String, state: &State) -> LspResult {
+    LspResult::Diagnostics(diagnostics_result(uri, state))
+}
+
+fn diagnostics_result(uri: String, state: &State) -> DiagnosticsResult {
+    let settings = &state.settings;
+    let diagnostics = state
+        .get_document(&uri)
+        .map(|doc| {
+            let root = state.find_root_config(&uri);
+            let graph = DependencyGraph::from_documents(&state.get_config_documents(&root));
+            let complete = !state.has_unresolved_mixins(&root);
+            let mut diagnostics = document_diagnostics(doc, &graph, complete, None);
+            diagnostics.extend(
+                state
+                    .lint_diagnostics
+                    .get(&uri)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+            settings.diagnostics.apply(diagnostics)
+        })
+        .unwrap_or_default();
+    DiagnosticsResult { uri, diagnostics }
+}
+
+// Files which may be mixins of configs.
+const WATCHED_FILES: [&str; 2] = ["**/lets*.yaml", "**/.lets/**"];
+
+// Ask the client to send `workspace/didChangeConfiguration` when settings change
+// and `workspace/didChangeWatchedFiles` when configs change on disk.
+pub fn register_capabilities(
+    state: &mut State,
+    configuration: bool,
+    watched_files: bool,
+) -> Option<LspResult> {
+    let mut registrations = vec![];
+    if configuration {
+        registrations.push(Registration {
+            id: "workspace/didChangeConfiguration".to_string(),
+            method: "workspace/didChangeConfiguration".to_string(),
+            register_options: None,
+        });
+    }
+    if watched_files {
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: WATCHED_FILES
+                .iter()
+                .map(|pattern| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(pattern.to_string()),
+                    kind: None,
+                })
+                .collect(),
+        };
+        registrations.push(Registration {
+            id: "workspace/didChangeWatchedFiles".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        });
+    }
+    if registrations.is_empty() {
+        return None;
+    }
+    Some(LspResult::ClientRequest(ClientRequestResult {
+        request: Request::new(
+            state.next_request_id(),
+            "client/registerCapability".to_string(),
+            RegistrationParams { registrations },
+        ),
+    }))
+}
+
+// lets keeps checksums in `.lets/` too, only YAML files may be configs or mixins.
+fn is_yaml(uri: &str) -> bool {
+    uri_to_path(uri).is_some_and(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "yaml" || extension == "yml")
+    })
+}
+
+// Files which are not open are read from disk on demand, so only diagnostics
+// of open documents which use the changed files need to be re-published.
+pub fn handle_did_change_watched_files(notf: Notification, state: &mut State) -> Option<LspResult> {
+    let params: DidChangeWatchedFilesParams = serde_json::from_value(notf.params).ok()?;
+    let changes: Vec<&str> = params
+        .changes
+        .iter()
+        .map(|change| change.uri.as_str())
+        .filter(|uri| is_yaml(uri))
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+    for change in &changes {
+        state.update_workspace_config(change);
+    }
+    let uris: Vec<String> = state
+        .documents
+        .keys()
+        .filter(|uri| {
+            let files = state.dependency_files(uri);
+            changes
+                .iter()
+                .any(|change| files.iter().any(|file| same_file(file, change)))
+        })
+        .cloned()
+        .collect();
+    refresh_diagnostics(uris, state)
+}
+
+// Pull settings from the client, the answer is handled in `handle_response`.
+pub fn request_configuration(state: &mut State) -> Option<LspResult> {
+    if !state.supports_configuration {
+        return None;
+    }
+    let id = state.next_request_id();
+    state.configuration_request = Some(id.clone());
+    let params = ConfigurationParams {
+        items: vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some(settings::SECTION.to_string()),
+        }],
+    };
+    Some(LspResult::ClientRequest(ClientRequestResult {
+        request: Request::new(id, "workspace/configuration".to_string(), params),
+    }))
+}
+
+// Clients with `workspace/configuration` support may send empty settings,
+// so settings are pulled from them instead.
+pub fn handle_did_change_configuration(notf: Notification, state: &mut State) -> Option<LspResult> {
+    if state.supports_configuration {
+        return request_configuration(state);
+    }
+    let params: DidChangeConfigurationParams = serde_json::from_value(notf.params).ok()?;
+    let options = settings::merge_options(&state.initialization_options, &params.settings);
+    apply_settings(Settings::from_options(&options), state)
+}
+
+pub fn handle_response(resp: Response, state: &mut State) -> Option<LspResult> {
+    if state.configuration_request.as_ref() != Some(&resp.id) {
+        return None;
+    }
+    state.configuration_request = None;
+    // one value per requested section, null if the client has no settings for it
+    let settings = resp.result?.as_array()?.first()?.clone();
+    if settings.is_null() {
+        return None;
+    }
+    let options = settings::merge_options(&state.initialization_options, &settings);
+    apply_settings(Settings::from_options(&options), state)
+}
+
+// Use new settings and re-publish diagnostics of open documents if they changed.
+fn apply_settings(settings: Settings, state: &mut State) -> Option<LspResult> {
+    if settings == state.settings {
+        return None;
     }
-    Some(LspResult::OK)
+    logging::set_client_level(settings.log_level);
+    let relint = settings.linter != state.settings.linter;
+    state.settings = settings;
+    let uris: Vec<String> = state.documents.keys().cloned().collect();
+    if relint {
+        for uri in &uris {
+            lint_document(uri, state);
+        }
+    }
+    refresh_diagnostics(uris, state)
+}
+
+// Re-publish diagnostics of the documents.
+fn refresh_diagnostics(mut uris: Vec<String>, state: &State) -> Option<LspResult> {
+    if uris.is_empty() {
+        return None;
+    }
+    uris.sort();
+    Some(LspResult::RefreshDiagnostics(
+        uris.into_iter()
+            .map(|uri| diagnostics_result(uri, state))
+            .collect(),
+    ))
+}
+
+// Root configs of the folder are found, documents may belong to them now.
+pub fn handle_workspace_folder_scanned(
+    folder: WorkspaceFolder,
+    state: &mut State,
+) -> Option<LspResult> {
+    if !state.set_workspace_folder(folder) {
+        return None;
+    }
+    refresh_diagnostics(state.documents.keys().cloned().collect(), state)
+}
+
+// Configs may move to another root when folders change, so all open documents are re-checked.
+pub fn handle_did_change_workspace_folders(
+    notf: Notification,
+    state: &mut State,
+) -> Option<LspResult> {
+    let params: DidChangeWorkspaceFoldersParams = serde_json::from_value(notf.params).ok()?;
+    for folder in params.event.removed {
+        state.remove_workspace_folder(folder.uri.as_str());
+    }
+    for folder in params.event.added {
+        state.add_workspace_folder(folder.uri.as_str());
+    }
+    refresh_diagnostics(state.documents.keys().cloned().collect(), state)
 }
 
 // Construct a new URI from the current URI and the filename and return it if file exists.
 // uri: current URI in format file://path/to/file
 // filename: filename to append to the current URI, e.g. "lets.my.yaml"
 fn go_to_def_uri(uri: &str, filename: &str) -> Option<String> {
-    let parent = std::path::Path::new(uri.strip_prefix("file://")?).parent()?;
-    let file = parent.join(filename);
-    if file.exists() {
-        return Some(format!("file://{}", file.to_str()?));
+    let uri = mixin_uri(uri, filename)?;
+    if uri_to_path(&uri)?.exists() {
+        return Some(uri);
     }
     None
 }
@@ -85,25 +494,97 @@ pub fn handle_definition(req: Request, state: &mut State) -> Option<LspResult> {
 
     let parser = Parser::new();
 
-    if !matches!(parser.get_position_type(doc, &pos), PositionType::Mixins) {
-        return None;
-    }
+    let location = match parser.get_position_type(doc, &pos) {
+        PositionType::Mixins => {
+            let filename = parser.extract_filename(doc, &pos)?;
+            let uri = go_to_def_uri(uri, &filename)?;
+            Location::new(uri.parse().ok()?, Range::default())
+        }
+        PositionType::Script => {
+            let var = find_env_declaration(&parser, doc, &pos)?;
+            Location::new(uri.parse().ok()?, var.range)
+        }
+        _ => return None,
+    };
+
+    Some(LspResult::Definition(DefinitionResult {
+        id: req.id,
+        value: DefinitionResultType::Scalar(lsp_types::lsif::LocationOrRangeId::Location(location)),
+    }))
+}
 
-    let filename = parser.extract_filename(doc, &pos)?;
+// Find declaration of env variable referenced under the cursor.
+// Command env shadows global env.
+fn find_env_declaration(parser: &Parser, doc: &str, pos: &Position) -> Option<EnvVar> {
+    let name = parser.get_env_reference(doc, pos)?;
+    let command_env = parser
+        .get_current_command(doc, pos)
+        .map(|command| parser.get_command_env(doc, &command.name))
+        .unwrap_or_default();
 
-    let uri = go_to_def_uri(uri, &filename);
-    match uri {
-        Some(uri) => {
-            let result =
-                DefinitionResultType::Scalar(lsp_types::lsif::LocationOrRangeId::Location(
-                    Location::new(uri.parse().ok()?, Range::default()),
-                ));
-            Some(LspResult::Definition(DefinitionResult {
-                id: req.id,
-                value: result,
-            }))
+    command_env
+        .into_iter()
+        .chain(parser.get_global_env(doc))
+        .find(|var| var.name == name)
+}
+
+pub fn handle_hover(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: HoverParams = serde_json::from_value(req.params).ok()?;
+    let uri = params
+        .text_document_position_params
+        .text_document
+        .uri
+        .as_str();
+    let doc = state.get_document(uri)?;
+    let pos = params.text_document_position_params.position;
+    let parser = Parser::new();
+
+    let contents = match parser.get_position_type(doc, &pos) {
+        PositionType::Script => match find_env_declaration(&parser, doc, &pos) {
+            Some(var) => env_hover(&var),
+            None => builtin_env_hover(&parser, doc, &pos)?,
+        },
+        _ => return None,
+    };
+
+    Some(LspResult::Hover(HoverResult {
+        id: req.id,
+        value: Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: contents,
+            }),
+            range: None,
+        },
+    }))
+}
+
+fn builtin_env_hover(parser: &Parser, doc: &str, pos: &Position) -> Option<String> {
+    let name = parser.get_env_reference(doc, pos)?;
+    let checksum_names = current_command_checksum_names(parser, doc, pos);
+    builtin_env(checksum_names.as_deref())
+        .into_iter()
+        .find(|var| var.name == name)
+        .map(|var| format!("`{}` (lets)\n\n{}", var.name, var.doc))
+}
+
+fn current_command_checksum_names(
+    parser: &Parser,
+    doc: &str,
+    pos: &Position,
+) -> Option<Vec<String>> {
+    let command = parser.get_current_command(doc, pos)?;
+    parser.get_command_checksum_names(doc, &command.name)
+}
+
+fn env_hover(var: &EnvVar) -> String {
+    match &var.value {
+        EnvValue::Literal(value) => format!("`{}` = `{}`", var.name, value),
+        EnvValue::Sh(script) => format!("`{}` = output of\n```sh\n{}\n```", var.name, script),
+        EnvValue::Checksum(files) => {
+            let files: Vec<String> = files.iter().map(|f| format!("- `{f}`")).collect();
+            format!("`{}` = checksum of\n{}", var.name, files.join("\n"))
         }
-        None => None,
     }
 }
 
@@ -117,11 +598,22 @@ pub fn handle_completion(req: Request, state: &mut State) -> Option<LspResult> {
 
     let items = match position_type {
         PositionType::Depends => {
-            let commands = parser.get_commands(doc);
+            // commands of the document go first, then commands of its config and mixins
+            let mut commands = parser.get_commands(doc);
+            for (config_uri, config) in state.get_config_documents(&state.find_root_config(uri)) {
+                if !same_file(&config_uri, uri) {
+                    for command in parser.get_commands(&config) {
+                        if !commands.iter().any(|known| known.name == command.name) {
+                            commands.push(command);
+                        }
+                    }
+                }
+            }
             let current_command = parser.get_current_command(doc, &position)?;
             on_completion_depends(&current_command, &commands).ok()?
         }
         PositionType::Mixins => on_completion_mixins().ok()?,
+        PositionType::Script => on_completion_env(&parser, doc, &position),
         _ => vec![],
     };
     Some(LspResult::Completion(CompletionResult {
@@ -130,6 +622,247 @@ pub fn handle_completion(req: Request, state: &mut State) -> Option<LspResult> {
     }))
 }
 
+pub fn handle_semantic_tokens_full(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: SemanticTokensParams = serde_json::from_value(req.params).ok()?;
+    let doc = state.get_document(params.text_document.uri.as_str())?;
+    Some(LspResult::SemanticTokens(SemanticTokensResult {
+        id: req.id,
+        value: SemanticTokens {
+            result_id: None,
+            data: semantic_tokens(doc, None),
+        },
+    }))
+}
+
+pub fn handle_semantic_tokens_range(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: SemanticTokensRangeParams = serde_json::from_value(req.params).ok()?;
+    let doc = state.get_document(params.text_document.uri.as_str())?;
+    Some(LspResult::SemanticTokens(SemanticTokensResult {
+        id: req.id,
+        value: SemanticTokens {
+            result_id: None,
+            data: semantic_tokens(doc, Some(params.range)),
+        },
+    }))
+}
+
+pub fn handle_code_lens(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: CodeLensParams = serde_json::from_value(req.params).ok()?;
+    let uri = params.text_document.uri.as_str();
+    let doc = state.get_document(uri)?;
+    Some(LspResult::CodeLens(CodeLensResult {
+        id: req.id,
+        value: code_lenses(&Parser::new(), doc, uri),
+    }))
+}
+
+// "▶ run" lens above each command and "▶ run with args…" for commands with `options`.
+fn code_lenses(parser: &Parser, doc: &str, uri: &str) -> Vec<CodeLens> {
+    let lens = |command: &Command, title: &str, lsp_command: &str| CodeLens {
+        range: command.range,
+        command: Some(lsp_types::Command {
+            title: title.to_string(),
+            command: lsp_command.to_string(),
+            arguments: Some(vec![
+                serde_json::json!(uri),
+                serde_json::json!(command.name),
+            ]),
+        }),
+        data: None,
+    };
+
+    let mut lenses = vec![];
+    for command in parser.get_commands(doc) {
+        lenses.push(lens(&command, "▶ run", RUN_COMMAND));
+        if parser.command_has_key(doc, &command.name, "options") {
+            lenses.push(lens(&command, "▶ run with args…", RUN_WITH_ARGS_COMMAND));
+        }
+    }
+    lenses
+}
+
+pub fn handle_code_action(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: CodeActionParams = serde_json::from_value(req.params).ok()?;
+    let uri = &params.text_document.uri;
+    let doc = state.get_document(uri.as_str())?;
+    let known_commands = state.get_known_commands(uri.as_str());
+    let diagnostics = &params.context.diagnostics;
+    let mut actions = did_you_mean_actions(doc, uri, &params.range, &known_commands, diagnostics);
+    actions.extend(create_command_actions(
+        doc,
+        uri,
+        &params.range,
+        &known_commands,
+        diagnostics,
+    ));
+    actions.extend(convert_sequence_actions(doc, uri, &params.range));
+    actions.extend(extract_command_actions(doc, uri, &params.range));
+    actions.extend(sort_commands_actions(doc, uri));
+    Some(LspResult::CodeAction(CodeActionResult {
+        id: req.id,
+        value: actions,
+    }))
+}
+
+pub fn handle_formatting(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: DocumentFormattingParams = serde_json::from_value(req.params).ok()?;
+    let doc = state.get_document(params.text_document.uri.as_str())?;
+    Some(LspResult::Formatting(FormattingResult {
+        id: req.id,
+        value: format_document(
+            doc,
+            &state.settings.format,
+            params.options.tab_size as usize,
+        ),
+    }))
+}
+
+pub fn handle_range_formatting(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: DocumentRangeFormattingParams = serde_json::from_value(req.params).ok()?;
+    let doc = state.get_document(params.text_document.uri.as_str())?;
+    Some(LspResult::Formatting(FormattingResult {
+        id: req.id,
+        value: format_range(
+            doc,
+            &params.range,
+            &state.settings.format,
+            params.options.tab_size as usize,
+        ),
+    }))
+}
+
+pub fn handle_folding_range(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: FoldingRangeParams = serde_json::from_value(req.params).ok()?;
+    let doc = state.get_document(params.text_document.uri.as_str())?;
+    Some(LspResult::FoldingRange(FoldingRangeResult {
+        id: req.id,
+        value: folding_ranges(doc),
+    }))
+}
+
+pub fn handle_selection_range(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: SelectionRangeParams = serde_json::from_value(req.params).ok()?;
+    let doc = state.get_document(params.text_document.uri.as_str())?;
+    Some(LspResult::SelectionRange(SelectionRangeResult {
+        id: req.id,
+        value: selection_ranges(doc, &params.positions),
+    }))
+}
+
+pub fn handle_document_highlight(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: DocumentHighlightParams = serde_json::from_value(req.params).ok()?;
+    let position = params.text_document_position_params;
+    let doc = state.get_document(position.text_document.uri.as_str())?;
+    Some(LspResult::DocumentHighlight(DocumentHighlightResult {
+        id: req.id,
+        value: document_highlights(&Parser::new(), doc, &position.position),
+    }))
+}
+
+// Command under cursor (its key or usage in `depends` and `ref`) is highlighted
+// as `Write` where it is defined and as `Read` where it is used.
+fn document_highlights(parser: &Parser, doc: &str, pos: &Position) -> Vec<DocumentHighlight> {
+    let contains = |range: &Range| range.start <= *pos && *pos <= range.end;
+    let commands = parser.get_commands(doc);
+    let references = parser.get_command_references(doc);
+    let name = commands
+        .iter()
+        .find(|command| contains(&command.range))
+        .map(|command| command.name.clone())
+        .or_else(|| {
+            references
+                .iter()
+                .find(|reference| contains(&reference.range))
+                .map(|reference| reference.name.clone())
+        });
+    let Some(name) = name else {
+        return vec![];
+    };
+
+    let definitions = commands
+        .into_iter()
+        .filter(|command| command.name == name)
+        .map(|command| DocumentHighlight {
+            range: command.range,
+            kind: Some(DocumentHighlightKind::WRITE),
+        });
+    let usages = references
+        .into_iter()
+        .filter(|reference| reference.name == name)
+        .map(|reference| DocumentHighlight {
+            range: reference.range,
+            kind: Some(DocumentHighlightKind::READ),
+        });
+    definitions.chain(usages).collect()
+}
+
+pub fn handle_prepare_call_hierarchy(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: CallHierarchyPrepareParams = serde_json::from_value(req.params).ok()?;
+    let position = params.text_document_position_params;
+    let uri = position.text_document.uri.as_str();
+    let documents = state.get_config_documents(&state.find_root_config(uri));
+    Some(LspResult::PrepareCallHierarchy(
+        PrepareCallHierarchyResult {
+            id: req.id,
+            value: prepare(&documents, uri, &position.position),
+        },
+    ))
+}
+
+pub fn handle_incoming_calls(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: CallHierarchyIncomingCallsParams = serde_json::from_value(req.params).ok()?;
+    let uri = params.item.uri.as_str();
+    let documents = state.get_config_documents(&state.find_root_config(uri));
+    Some(LspResult::IncomingCalls(IncomingCallsResult {
+        id: req.id,
+        value: incoming_calls(&documents, &params.item),
+    }))
+}
+
+pub fn handle_outgoing_calls(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: CallHierarchyOutgoingCallsParams = serde_json::from_value(req.params).ok()?;
+    let uri = params.item.uri.as_str();
+    let documents = state.get_config_documents(&state.find_root_config(uri));
+    Some(LspResult::OutgoingCalls(OutgoingCallsResult {
+        id: req.id,
+        value: outgoing_calls(&documents, &params.item),
+    }))
+}
+
+pub fn handle_execute_command(req: Request, state: &mut State) -> Option<LspResult> {
+    let params: ExecuteCommandParams = serde_json::from_value(req.params).ok()?;
+    match params.command.as_str() {
+        // the lens has no args, the client has to ask for them and append them
+        RUN_WITH_ARGS_COMMAND if !params.arguments.get(2).is_some_and(|args| args.is_array()) => {
+            Some(LspResult::Error(ErrorResult {
+                id: req.id,
+                code: ErrorCode::InvalidParams,
+                message: format!(
+                    "{RUN_WITH_ARGS_COMMAND} expects a list of args as the third argument"
+                ),
+            }))
+        }
+        RUN_COMMAND | RUN_WITH_ARGS_COMMAND => Some(LspResult::Run(RunResult {
+            id: req.id,
+            request: RunRequest::from_arguments(&params.arguments)?,
+        })),
+        // arguments: [uri of any config document, optional format: "dot" or "mermaid"]
+        GRAPH_COMMAND => {
+            let uri = params.arguments.first()?.as_str()?;
+            let format = match params.arguments.get(1).and_then(|format| format.as_str()) {
+                Some(format) => GraphFormat::from_str(format, true).ok()?,
+                None => GraphFormat::default(),
+            };
+            let documents = state.get_config_documents(&state.find_root_config(uri));
+            Some(LspResult::DependencyGraph(DependencyGraphResult {
+                id: req.id,
+                value: DependencyGraph::from_documents(&documents).render(format),
+            }))
+        }
+        _ => None,
+    }
+}
+
 fn on_completion_depends(
     current_command: &Command,
     commands: &[Command],
@@ -148,6 +881,46 @@ fn on_completion_depends(
         .collect()
 }
 
+// Complete declared and builtin env variables after `$` in scripts.
+fn on_completion_env(parser: &Parser, doc: &str, pos: &Position) -> Vec<LSPCompletion> {
+    let Some(line) = doc.lines().nth(pos.line as usize) else {
+        return vec![];
+    };
+    if env_prefix_before_cursor(line, pos.character as usize).is_none() {
+        return vec![];
+    }
+
+    let command = parser.get_current_command(doc, pos);
+    let command_env = command
+        .as_ref()
+        .map(|command| parser.get_command_env(doc, &command.name))
+        .unwrap_or_default();
+
+    let mut items: Vec<LSPCompletion> = vec![];
+    for var in command_env.iter().chain(parser.get_global_env(doc).iter()) {
+        if items.iter().any(|item| item.label == var.name) {
+            continue;
+        }
+        items.push(LSPCompletion {
+            label: var.name.clone(),
+            details: Some(env_hover(var)),
+            location: None,
+        });
+    }
+
+    let checksum_names = current_command_checksum_names(parser, doc, pos);
+    items.extend(
+        builtin_env(checksum_names.as_deref())
+            .into_iter()
+            .map(|var| LSPCompletion {
+                label: var.name,
+                details: Some(var.doc),
+                location: None,
+            }),
+    );
+    items
+}
+
 fn on_completion_mixins() -> anyhow::Result<Vec<LSPCompletion>> {
     // walk current dir or take word as dir if with /
     Ok(vec![])
@@ -220,4 +993,521 @@ commands:
         assert_eq!(result[0].label, "test2");
         assert!(result[0].location.is_none());
     }
+
+    #[test]
+    fn test_complete_depends_with_mixins() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, text: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write(
+            "services/api/lets.yaml",
+            "mixins: [.lets/db.yaml]\ncommands:\n  serve-api:\n    depends: []\n    cmd: echo",
+        );
+        write(
+            "services/api/.lets/db.yaml",
+            "commands:\n  migrate:\n    depends: []\n    cmd: echo\n  seed:\n    cmd: echo",
+        );
+        write(
+            "services/web/lets.yaml",
+            "commands:\n  serve-web:\n    cmd: echo",
+        );
+        let uri = |path: &str| format!("file://{}/{}", dir.path().display(), path);
+
+        let mut state = State::new();
+        state.add_workspace_folder(&format!("file://{}", dir.path().display()));
+        let mut complete = |path: &str, position: Position| {
+            let doc = std::fs::read_to_string(dir.path().join(path)).unwrap();
+            state.add_document(uri(path), doc);
+            let req = Request::new(
+                RequestId::from(1),
+                "textDocument/completion".to_string(),
+                serde_json::json!({
+                    "textDocument": {"uri": uri(path)},
+                    "position": position,
+                }),
+            );
+            let Some(LspResult::Completion(result)) = handle_completion(req, &mut state) else {
+                panic!("expected completion");
+            };
+            result
+                .list
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+
+        // commands of the document go first, commands of the other root are not offered
+        assert_eq!(
+            complete("services/api/.lets/db.yaml", Position::new(2, 14)),
+            vec!["seed", "serve-api"]
+        );
+        assert_eq!(
+            complete("services/api/lets.yaml", Position::new(3, 14)),
+            vec!["migrate", "seed"]
+        );
+    }
+
+    #[test]
+    fn test_find_env_declaration() {
+        let doc = r#"
+shell: bash
+env:
+  FOO: global
+  BAR:
+    sh: echo bar
+before: echo $FOO
+commands:
+  test:
+    env:
+      FOO: local
+    cmd: echo $FOO ${BAR}
+  test2:
+    cmd: echo $FOO"#
+            .trim();
+
+        let parser = Parser::new();
+        let tests = vec![
+            (Position::new(5, 15), Some((Position::new(2, 2), "global"))),
+            (Position::new(10, 16), Some((Position::new(9, 6), "local"))),
+            (
+                Position::new(10, 21),
+                Some((Position::new(3, 2), "echo bar")),
+            ),
+            (Position::new(12, 16), Some((Position::new(2, 2), "global"))),
+            (Position::new(10, 8), None),
+        ];
+        for (i, (pos, expect)) in tests.into_iter().enumerate() {
+            let result = find_env_declaration(&parser, doc, &pos);
+            let expect = expect.map(|(start, value)| (start, value.to_string()));
+            let result = result.map(|var| {
+                let value = match var.value {
+                    EnvValue::Literal(value) | EnvValue::Sh(value) => value,
+                    EnvValue::Checksum(_) => String::new(),
+                };
+                (var.range.start, value)
+            });
+            assert_eq!(
+                result, expect,
+                "Case {i}: expected {expect:?}, actual {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_env_hover() {
+        let var = EnvVar {
+            name: "BAR".to_string(),
+            value: EnvValue::Sh("echo bar".to_string()),
+            range: Range::default(),
+        };
+        assert_eq!(env_hover(&var), "`BAR` = output of\n```sh\necho bar\n```");
+    }
+
+    #[test]
+    fn test_complete_env() {
+        let doc = r#"
+shell: bash
+env:
+  FOO: global
+commands:
+  test:
+    env:
+      FOO: local
+    checksum:
+      deps: [requirements.txt]
+    cmd: echo $
+  test2:
+    cmd: echo $"#
+            .trim();
+
+        let parser = Parser::new();
+        let labels = |pos: Position| -> Vec<String> {
+            on_completion_env(&parser, doc, &pos)
+                .into_iter()
+                .map(|item| item.label)
+                .collect()
+        };
+
+        let result = labels(Position::new(9, 15));
+        assert_eq!(result[0], "FOO");
+        assert_eq!(result.iter().filter(|l| *l == "FOO").count(), 1);
+        assert!(result.contains(&"LETS_COMMAND_NAME".to_string()));
+        assert!(result.contains(&"LETS_CHECKSUM_DEPS".to_string()));
+
+        let result = labels(Position::new(11, 15));
+        assert!(result.contains(&"LETS_COMMAND_NAME".to_string()));
+        assert!(!result.iter().any(|l| l.starts_with("LETS_CHECKSUM")));
+
+        assert!(labels(Position::new(9, 13)).is_empty());
+    }
+
+    #[test]
+    fn test_builtin_env_hover() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    checksum:
+      deps: [requirements.txt]
+    cmd: echo $LETS_CHECKSUM_DEPS $LETS_CONFIG_DIR
+  test2:
+    cmd: echo $LETS_CHECKSUM_DEPS"#
+            .trim();
+
+        let parser = Parser::new();
+        let hover = builtin_env_hover(&parser, doc, &Position::new(5, 20)).unwrap();
+        assert!(hover.starts_with("`LETS_CHECKSUM_DEPS` (lets)"));
+        let hover = builtin_env_hover(&parser, doc, &Position::new(5, 40)).unwrap();
+        assert!(hover.starts_with("`LETS_CONFIG_DIR` (lets)"));
+        assert_eq!(builtin_env_hover(&parser, doc, &Position::new(7, 20)), None);
+    }
+
+    #[test]
+    fn test_code_lenses() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    cmd: echo Test
+  release:
+    options: |
+      Usage: lets release <version>
+    cmd: echo Release"#
+            .trim();
+
+        let lenses = code_lenses(&Parser::new(), doc, "file:///project/lets.yaml");
+        let lenses: Vec<(u32, String, serde_json::Value)> = lenses
+            .into_iter()
+            .map(|lens| {
+                let command = lens.command.unwrap();
+                (
+                    lens.range.start.line,
+                    command.title,
+                    serde_json::json!(command.arguments),
+                )
+            })
+            .collect();
+        let arguments = |name: &str| serde_json::json!(["file:///project/lets.yaml", name]);
+        assert_eq!(
+            lenses,
+            vec![
+                (2, "▶ run".to_string(), arguments("test")),
+                (4, "▶ run".to_string(), arguments("release")),
+                (4, "▶ run with args…".to_string(), arguments("release")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_with_args_requires_args() {
+        let mut state = State::new();
+        let mut execute = |command: &str, arguments: serde_json::Value| {
+            let req = Request::new(
+                RequestId::from(1),
+                "workspace/executeCommand".to_string(),
+                serde_json::json!({"command": command, "arguments": arguments}),
+            );
+            handle_execute_command(req, &mut state)
+        };
+
+        let result = execute(
+            RUN_WITH_ARGS_COMMAND,
+            serde_json::json!(["file:///project/lets.yaml", "release"]),
+        );
+        let Some(LspResult::Error(error)) = result else {
+            panic!("expected error, got {result:?}");
+        };
+        assert!(matches!(error.code, ErrorCode::InvalidParams));
+
+        let result = execute(
+            RUN_WITH_ARGS_COMMAND,
+            serde_json::json!(["file:///project/lets.yaml", "release", ["1.0"]]),
+        );
+        let Some(LspResult::Run(run)) = result else {
+            panic!("expected run, got {result:?}");
+        };
+        assert_eq!(run.request.args, vec!["1.0".to_string()]);
+
+        let result = execute(
+            RUN_COMMAND,
+            serde_json::json!(["file:///project/lets.yaml", "release"]),
+        );
+        assert!(matches!(result, Some(LspResult::Run(_))));
+    }
+
+    #[test]
+    fn test_document_highlights() {
+        let doc = r#"
+shell: bash
+commands:
+  build:
+    cmd: echo Build
+  test:
+    depends: [build]
+    cmd: echo Test
+  release:
+    ref: build"#
+            .trim();
+
+        let expected = vec![
+            (2, DocumentHighlightKind::WRITE),
+            (5, DocumentHighlightKind::READ),
+            (8, DocumentHighlightKind::READ),
+        ];
+        for pos in [Position::new(2, 3), Position::new(5, 15)] {
+            let highlights: Vec<(u32, DocumentHighlightKind)> =
+                document_highlights(&Parser::new(), doc, &pos)
+                    .into_iter()
+                    .map(|highlight| (highlight.range.start.line, highlight.kind.unwrap()))
+                    .collect();
+            assert_eq!(highlights, expected);
+        }
+
+        assert!(document_highlights(&Parser::new(), doc, &Position::new(3, 10)).is_empty());
+    }
+
+    #[test]
+    fn test_did_change_watched_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = format!("file://{}/lets.yaml", dir.path().display());
+        let mixin = format!("file://{}/lets.build.yaml", dir.path().display());
+        let other = format!("file://{}/lets.other.yaml", dir.path().display());
+        std::fs::write(
+            dir.path().join("lets.build.yaml"),
+            "commands:\n  build:\n    cmd: echo Build",
+        )
+        .unwrap();
+
+        let mut state = State::new();
+        state.add_document(
+            root.clone(),
+            "mixins: [lets.build.yaml]\ncommands:\n  test:\n    depends: [build]".to_string(),
+        );
+        let notification = |uri: &str| Notification {
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            params: serde_json::json!({"changes": [{"uri": uri, "type": 2}]}),
+        };
+        let refreshed = |result: Option<LspResult>| match result {
+            Some(LspResult::RefreshDiagnostics(results)) => results
+                .into_iter()
+                .map(|result| (result.uri, result.diagnostics.len()))
+                .collect(),
+            _ => vec![],
+        };
+
+        let result = handle_did_change_watched_files(notification(&mixin), &mut state);
+        assert_eq!(refreshed(result), vec![(root.clone(), 0)]);
+
+        // deleted mixin is still a part of the config
+        std::fs::remove_file(dir.path().join("lets.build.yaml")).unwrap();
+        let result = handle_did_change_watched_files(notification(&mixin), &mut state);
+        assert_eq!(refreshed(result), vec![(root.clone(), 1)]);
+
+        assert!(handle_did_change_watched_files(notification(&other), &mut state).is_none());
+
+        // checksum files written by lets are ignored
+        let checksum = format!("file://{}/.lets/checksum_test", dir.path().display());
+        assert!(handle_did_change_watched_files(notification(&checksum), &mut state).is_none());
+    }
+
+    #[test]
+    fn test_linter_runs_on_open_and_save() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let runs = dir.path().join("runs");
+        let linter = dir.path().join("fake-linter");
+        std::fs::write(
+            &linter,
+            format!(
+                "#!/bin/sh\ncat > /dev/null\necho run >> {}\necho '[{{\"line\":1,\"column\":1,\"level\":\"info\",\"code\":2086,\"message\":\"Quote\"}}]'\n",
+                runs.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&linter, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let run_count = || {
+            std::fs::read_to_string(&runs)
+                .map(|runs| runs.lines().count())
+                .unwrap_or(0)
+        };
+
+        let mut state = State::new();
+        state.settings = Settings::from_options(
+            &serde_json::json!({"linter": {"path": linter.to_str().unwrap()}}),
+        );
+        let uri = format!("file://{}/lets.yaml", dir.path().display());
+        let doc = "commands:\n  test:\n    cmd: echo $FOO\n";
+        let diagnostics = |result: Option<LspResult>| match result {
+            Some(LspResult::Diagnostics(result)) => result.diagnostics.len(),
+            _ => panic!("expected diagnostics"),
+        };
+        let notification = |method: &str, params: serde_json::Value| Notification {
+            method: method.to_string(),
+            params,
+        };
+
+        let result = handle_didOpen(
+            notification(
+                "textDocument/didOpen",
+                serde_json::json!({"textDocument": {
+                    "uri": uri, "languageId": "yaml", "version": 1, "text": doc
+                }}),
+            ),
+            &mut state,
+        );
+        assert_eq!(diagnostics(result), 1);
+        assert_eq!(run_count(), 1);
+
+        // findings are kept, but the linter does not run until the document is saved
+        let result = handle_didChange(
+            notification(
+                "textDocument/didChange",
+                serde_json::json!({
+                    "textDocument": {"uri": uri, "version": 2},
+                    "contentChanges": [{"text": doc}]
+                }),
+            ),
+            &mut state,
+        );
+        assert_eq!(diagnostics(result), 1);
+        assert_eq!(run_count(), 1);
+
+        let result = handle_did_save(
+            notification(
+                "textDocument/didSave",
+                serde_json::json!({"textDocument": {"uri": uri}}),
+            ),
+            &mut state,
+        );
+        assert_eq!(diagnostics(result), 1);
+        assert_eq!(run_count(), 2);
+    }
+
+    #[test]
+    fn test_linter_runs_in_background() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // fake linter reports the shell it was asked to lint for
+        let dir = tempfile::tempdir().unwrap();
+        let linter = dir.path().join("fake-linter");
+        std::fs::write(
+            &linter,
+            r#"#!/bin/sh
+cat > /dev/null
+echo "[{\"line\":1,\"column\":1,\"message\":\"$2\"}]"
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&linter, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = State::new();
+        state.lint_results = Some(sender);
+        state.settings = Settings::from_options(&serde_json::json!({"linter": {
+            "path": linter.to_str().unwrap(), "args": ["-s", "{shell}"]
+        }}));
+        let uri = format!("file://{}/lets.yaml", dir.path().display());
+        let open = |state: &mut State| {
+            handle_didOpen(
+                Notification {
+                    method: "textDocument/didOpen".to_string(),
+                    params: serde_json::json!({"textDocument": {
+                        "uri": uri, "languageId": "yaml", "version": 1,
+                        "text": "shell: /bin/sh\ncommands:\n  test:\n    cmd: echo $FOO\n"
+                    }}),
+                },
+                state,
+            )
+        };
+
+        // diagnostics are published without waiting for the linter
+        let Some(LspResult::Diagnostics(result)) = open(&mut state) else {
+            panic!("expected diagnostics");
+        };
+        assert!(result.diagnostics.is_empty());
+        let first = receiver.recv().unwrap();
+
+        // only the latest lint of the document is published
+        open(&mut state);
+        let second = receiver.recv().unwrap();
+        assert!(handle_lint_finished(first, &mut state).is_none());
+        let Some(LspResult::Diagnostics(result)) = handle_lint_finished(second, &mut state) else {
+            panic!("expected diagnostics");
+        };
+        let messages: Vec<String> = result
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(messages, vec!["sh".to_string()]);
+    }
+
+    #[test]
+    fn test_configuration() {
+        let uri = "file:///tmp/lets.yaml".to_string();
+        let mut state = State::new();
+        state.initialization_options = serde_json::json!({"lets": {"path": "/opt/lets"}});
+        state.settings = Settings::from_options(&state.initialization_options);
+        state.add_document(
+            uri.clone(),
+            "commands:\n  test:\n    depends: [build]".to_string(),
+        );
+        let refreshed = |result: Option<LspResult>| match result {
+            Some(LspResult::RefreshDiagnostics(results)) => results
+                .into_iter()
+                .map(|result| (result.uri, result.diagnostics.len()))
+                .collect(),
+            _ => vec![],
+        };
+        let pull = |state: &mut State| {
+            let Some(LspResult::ClientRequest(result)) = request_configuration(state) else {
+                panic!("expected workspace/configuration request");
+            };
+            assert_eq!(result.request.method, "workspace/configuration");
+            result.request.id
+        };
+        let disabled = serde_json::json!({"diagnostics": {"disabled": ["undefined-dependency"]}});
+
+        // without `workspace/configuration` pushed settings are applied
+        let notification = Notification {
+            method: "workspace/didChangeConfiguration".to_string(),
+            params: serde_json::json!({"settings": {"lets_ls": disabled}}),
+        };
+        let result = handle_did_change_configuration(notification.clone(), &mut state);
+        assert_eq!(refreshed(result), vec![(uri.clone(), 0)]);
+        assert_eq!(state.settings.lets_path, "/opt/lets");
+
+        // same settings do not re-publish diagnostics
+        assert!(apply_settings(state.settings.clone(), &mut state).is_none());
+        let result = apply_settings(Settings::default(), &mut state);
+        assert_eq!(refreshed(result), vec![(uri.clone(), 1)]);
+
+        // otherwise settings are pulled and merged over initialization options
+        state.supports_configuration = true;
+        let Some(LspResult::ClientRequest(result)) =
+            handle_did_change_configuration(notification, &mut state)
+        else {
+            panic!("expected workspace/configuration request");
+        };
+        let id = result.request.id;
+        assert!(handle_response(
+            Response::new_ok(RequestId::from(99), [&disabled]),
+            &mut state
+        )
+        .is_none());
+        let result = handle_response(Response::new_ok(id, [&disabled]), &mut state);
+        assert_eq!(refreshed(result), vec![(uri.clone(), 0)]);
+        assert_eq!(state.settings.lets_path, "/opt/lets");
+
+        // empty settings keep initialization options
+        let id = pull(&mut state);
+        let result = handle_response(Response::new_ok(id, [serde_json::json!({})]), &mut state);
+        assert_eq!(refreshed(result), vec![(uri.clone(), 1)]);
+        assert_eq!(state.settings.lets_path, "/opt/lets");
+        assert!(state.configuration_request.is_none());
+    }
 }
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crossbeam_channel::Sender;
+use log::{LevelFilter, Log, Metadata, Record};
+use lsp_server::{Message, Notification, RequestId};
+use lsp_types::{LogMessageParams, LogTraceParams, MessageType, TraceValue};
+
+// Level of logs sent to the client unless set in initialization options.
+pub const DEFAULT_CLIENT_LEVEL: LevelFilter = LevelFilter::Info;
+
+struct Client {
+    sender: Sender<Message>,
+    level: LevelFilter,
+}
+
+static CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+// level of env_logger, e.g. from `RUST_LOG`
+static INNER_LEVEL: Mutex<LevelFilter> = Mutex::new(LevelFilter::Off);
+
+// Writes logs with env_logger and forwards logs of the server to the client
+// with `window/logMessage` once the connection is set.
+struct ClientLogger {
+    inner: env_logger::Logger,
+}
+
+// Only records of the server itself are forwarded, logs of lsp_server
+// about written messages would produce new messages otherwise.
+fn is_forwarded(metadata: &Metadata) -> bool {
+    metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+}
+
+fn client_level() -> LevelFilter {
+    CLIENT
+        .lock()
+        .ok()
+        .and_then(|client| client.as_ref().map(|client| client.level))
+        .unwrap_or(LevelFilter::Off)
+}
+
+impl Log for ClientLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+            || (is_forwarded(metadata) && metadata.level() <= client_level())
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.log(record);
+        if !is_forwarded(record.metadata()) {
+            return;
+        }
+        let sender = match CLIENT.lock().ok().as_deref() {
+            Some(Some(client)) if record.level() <= client.level => client.sender.clone(),
+            _ => return,
+        };
+        let typ = match record.level() {
+            log::Level::Error => MessageType::ERROR,
+            log::Level::Warn => MessageType::WARNING,
+            log::Level::Info => MessageType::INFO,
+            log::Level::Debug | log::Level::Trace => MessageType::LOG,
+        };
+        let params = LogMessageParams {
+            typ,
+            message: record.args().to_string(),
+        };
+        let _ = sender.send(Message::Notification(Notification::new(
+            "window/logMessage".to_string(),
+            params,
+        )));
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Records above the max level are skipped by `log` macros before reaching the logger.
+fn update_max_level() {
+    let inner = INNER_LEVEL
+        .lock()
+        .map(|level| *level)
+        .unwrap_or(LevelFilter::Off);
+    log::set_max_level(inner.max(client_level()));
+}
+
+pub fn init(builder: &mut env_logger::Builder) {
+    let inner = builder.build();
+    if let Ok(mut level) = INNER_LEVEL.lock() {
+        *level = inner.filter();
+    }
+    if log::set_boxed_logger(Box::new(ClientLogger { inner })).is_ok() {
+        update_max_level();
+    }
+}
+
+// Start forwarding logs to the client.
+pub fn set_client(sender: Sender<Message>, level: LevelFilter) {
+    if let Ok(mut client) = CLIENT.lock() {
+        *client = Some(Client { sender, level });
+    }
+    update_max_level();
+}
+
+pub fn set_client_level(level: LevelFilter) {
+    if let Ok(mut client) = CLIENT.lock() {
+        if let Some(client) = client.as_mut() {
+            client.level = level;
+        }
+    }
+    update_max_level();
+}
+
+// Stop forwarding logs, the connection can not be closed while the sender is alive.
+pub fn clear_client() {
+    if let Ok(mut client) = CLIENT.lock() {
+        *client = None;
+    }
+}
+
+// Read `{"log": {"level": "debug"}}` from initialization options.
+pub fn level_from_options(options: &serde_json::Value) -> Option<LevelFilter> {
+    LevelFilter::from_str(options.pointer("/log/level")?.as_str()?).ok()
+}
+
+// Traces messages with `$/logTrace` as requested by the client with `$/setTrace`.
+#[derive(Debug)]
+pub struct Tracer {
+    value: TraceValue,
+    // method and start time of requests being processed
+    pending: HashMap<RequestId, (String, Instant)>,
+}
+
+impl Tracer {
+    pub fn new(value: TraceValue) -> Self {
+        Self {
+            value,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, value: TraceValue) {
+        self.value = value;
+        if value == TraceValue::Off {
+            self.pending.clear();
+        }
+    }
+
+    fn trace(&self, message: String, params: &serde_json::Value) -> Option<Message> {
+        let verbose = match self.value {
+            TraceValue::Off => return None,
+            TraceValue::Messages => None,
+            TraceValue::Verbose => Some(format!(
+                "Params: {}",
+                serde_json::to_string_pretty(params).unwrap_or_default()
+            )),
+        };
+        let params = LogTraceParams { message, verbose };
+        Some(Message::Notification(Notification::new(
+            "$/logTrace".to_string(),
+            params,
+        )))
+    }
+
+    // Trace message received from the client.
+    pub fn incoming(&mut self, msg: &Message) -> Option<Message> {
+        if self.value == TraceValue::Off {
+            return None;
+        }
+        match msg {
+            Message::Request(req) => {
+                self.pending
+                    .insert(req.id.clone(), (req.method.clone(), Instant::now()));
+                self.trace(
+                    format!("Received request '{} - ({})'.", req.method, req.id),
+                    &req.params,
+                )
+            }
+            Message::Notification(notf) => self.trace(
+                format!("Received notification '{}'.", notf.method),
+                &notf.params,
+            ),
+            Message::Response(resp) => self.trace(
+                format!("Received response '({})'.", resp.id),
+                resp.result.as_ref().unwrap_or(&serde_json::Value::Null),
+            ),
+        }
+    }
+
+    // Trace message sent to the client.
+    pub fn outgoing(&mut self, msg: &Message) -> Option<Message> {
+        if self.value == TraceValue::Off {
+            return None;
+        }
+        match msg {
+            Message::Response(resp) => {
+                let message = match self.pending.remove(&resp.id) {
+                    Some((method, start)) => format!(
+                        "Sending response '{} - ({})'. Processing request took {}ms",
+                        method,
+                        resp.id,
+                        start.elapsed().as_millis()
+                    ),
+                    None => format!("Sending response '({})'.", resp.id),
+                };
+                let result = match &resp.error {
+                    Some(error) => serde_json::to_value(error).unwrap_or_default(),
+                    None => resp.result.clone().unwrap_or_default(),
+                };
+                self.trace(message, &result)
+            }
+            Message::Notification(notf) => self.trace(
+                format!("Sending notification '{}'.", notf.method),
+                &notf.params,
+            ),
+            Message::Request(req) => self.trace(
+                format!("Sending request '{} - ({})'.", req.method, req.id),
+                &req.params,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::{Request, Response};
+
+    fn log_trace(msg: Option<Message>) -> LogTraceParams {
+        let Some(Message::Notification(notf)) = msg else {
+            panic!("expected notification");
+        };
+        assert_eq!(notf.method, "$/logTrace");
+        serde_json::from_value(notf.params).unwrap()
+    }
+
+    #[test]
+    fn test_tracer() {
+        let request = Message::Request(Request::new(
+            RequestId::from(3),
+            "textDocument/completion".to_string(),
+            serde_json::json!({"position": {"line": 1, "character": 2}}),
+        ));
+        let response = Message::Response(Response::new_ok(RequestId::from(3), vec!["build"]));
+
+        let mut tracer = Tracer::new(TraceValue::Off);
+        assert!(tracer.incoming(&request).is_none());
+
+        tracer.set(TraceValue::Messages);
+        let params = log_trace(tracer.incoming(&request));
+        assert_eq!(
+            params.message,
+            "Received request 'textDocument/completion - (3)'."
+        );
+        assert_eq!(params.verbose, None);
+        let params = log_trace(tracer.outgoing(&response));
+        assert!(params.message.starts_with(
+            "Sending response 'textDocument/completion - (3)'. Processing request took"
+        ));
+
+        tracer.set(TraceValue::Verbose);
+        tracer.incoming(&request);
+        let params = log_trace(tracer.outgoing(&response));
+        assert_eq!(params.verbose.as_deref(), Some("Params: [\n  \"build\"\n]"));
+    }
+
+    #[test]
+    fn test_level_from_options() {
+        let options = serde_json::json!({"log": {"level": "debug"}});
+        assert_eq!(level_from_options(&options), Some(LevelFilter::Debug));
+        let options = serde_json::json!({"log": {"level": "loud"}});
+        assert_eq!(level_from_options(&options), None);
+    }
+}
@@ -1,38 +1,130 @@
 use std::error::Error;
 
+use clap::Parser as _;
 use lsp_server::{Connection, Message};
-use lsp_types::ServerCapabilities;
+use lsp_types::{ServerCapabilities, SetTraceParams, TraceValue};
 
 use crate::handler::{
-    handle_completion, handle_definition, handle_didChange, handle_didOpen, LspResult,
+    handle_code_action, handle_code_lens, handle_completion, handle_definition, handle_didChange,
+    handle_didOpen, handle_did_change_configuration, handle_did_change_watched_files,
+    handle_did_change_workspace_folders, handle_did_save, handle_document_highlight,
+    handle_execute_command, handle_folding_range, handle_formatting, handle_hover,
+    handle_incoming_calls, handle_lint_finished, handle_outgoing_calls,
+    handle_prepare_call_hierarchy, handle_range_formatting, handle_response,
+    handle_selection_range, handle_semantic_tokens_full, handle_semantic_tokens_range,
+    handle_workspace_folder_scanned, register_capabilities, request_configuration, LspResult,
 };
-use crate::responses::{completion_response, definition_response};
+use crate::logging::Tracer;
+use crate::responses::{
+    client_request, code_action_response, code_lens_response, completion_response,
+    definition_response, dependency_graph_response, diagnostics_notification,
+    document_highlight_response, error_response, folding_range_response, formatting_response,
+    hover_response, incoming_calls_response, outgoing_calls_response,
+    prepare_call_hierarchy_response, selection_range_response, semantic_tokens_response,
+};
+use crate::settings::Settings;
 use crate::state::State;
 
+pub mod builtins;
+pub mod call_hierarchy;
+pub mod cli;
+pub mod code_actions;
+pub mod diagnostics;
+pub mod folding;
+pub mod formatting;
+pub mod graph;
 pub mod handler;
+pub mod logging;
+pub mod report;
 pub mod responses;
+pub mod runner;
+pub mod selection;
+pub mod semantic_tokens;
+pub mod settings;
+pub mod shell;
 pub mod state;
+pub mod transport;
 pub mod treesitter;
 
-
-fn get_version() -> String {
-    let mut version = std::env!("CARGO_PKG_VERSION").to_string();
-    if cfg!(debug_assertions) {
-        version.push_str("-debug");
+// Convert the result into messages and send them to the client.
+fn send_result(
+    result: LspResult,
+    connection: &Connection,
+    state: &State,
+    tracer: &mut Tracer,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let messages = match result {
+        LspResult::OK => vec![],
+        LspResult::Definition(result) => vec![definition_response(result)?],
+        LspResult::Completion(result) => vec![completion_response(result)],
+        LspResult::Hover(result) => vec![hover_response(result)],
+        LspResult::SemanticTokens(result) => vec![semantic_tokens_response(result)],
+        LspResult::Diagnostics(result) => vec![diagnostics_notification(result)?],
+        LspResult::RefreshDiagnostics(results) => results
+            .into_iter()
+            .map(diagnostics_notification)
+            .collect::<Result<_, _>>()?,
+        LspResult::CodeLens(result) => vec![code_lens_response(result)],
+        LspResult::CodeAction(result) => vec![code_action_response(result)],
+        LspResult::Formatting(result) => vec![formatting_response(result)],
+        LspResult::FoldingRange(result) => vec![folding_range_response(result)],
+        LspResult::SelectionRange(result) => vec![selection_range_response(result)],
+        LspResult::DocumentHighlight(result) => vec![document_highlight_response(result)],
+        LspResult::PrepareCallHierarchy(result) => {
+            vec![prepare_call_hierarchy_response(result)]
+        }
+        LspResult::IncomingCalls(result) => vec![incoming_calls_response(result)],
+        LspResult::OutgoingCalls(result) => vec![outgoing_calls_response(result)],
+        LspResult::DependencyGraph(result) => vec![dependency_graph_response(result)],
+        LspResult::ClientRequest(result) => vec![client_request(result)],
+        LspResult::Error(result) => vec![error_response(result)],
+        LspResult::Run(result) => {
+            // commands may run for a long time, do not block the main loop
+            let sender = state
+                .run_output
+                .clone()
+                .unwrap_or_else(|| connection.sender.clone());
+            let lets_path = state.settings.lets_path.clone();
+            let runs = state.runs.clone();
+            std::thread::spawn(move || {
+                runner::run_and_report(&lets_path, result.id, result.request, &runs, sender)
+            });
+            vec![]
+        }
+    };
+    for message in messages {
+        if let Some(trace) = tracer.outgoing(&message) {
+            connection.sender.send(trace)?;
+        }
+        connection.sender.send(message)?;
     }
-    version.to_string()
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
-    env_logger::init();
-    log::info!("Lets LSP server starting (version: {})", get_version());
+    let cli = cli::Cli::parse();
+    if let Err(err) = cli.init_logging() {
+        eprintln!("lets_ls: can not open log file: {}", err);
+        std::process::exit(2);
+    }
+    if let Some(command) = cli.command {
+        std::process::exit(cli::run(command));
+    }
+
+    log::info!("Lets LSP server starting (version: {})", cli::VERSION);
 
-    let (connection, io_threads) = Connection::stdio();
-    let (id, _) = connection.initialize_start()?;
+    let (connection, io_threads) = cli.transport().connect()?;
+    let (id, params) = connection.initialize_start()?;
+    let params: lsp_types::InitializeParams = serde_json::from_value(params)?;
 
     let server_capabilities = ServerCapabilities {
-        text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Kind(
-            lsp_types::TextDocumentSyncKind::FULL,
+        text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Options(
+            lsp_types::TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(lsp_types::TextDocumentSyncKind::FULL),
+                save: Some(lsp_types::TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
         )),
         hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
         definition_provider: Some(lsp_types::OneOf::Left(true)),
@@ -52,6 +144,53 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
             all_commit_characters: None,
             completion_item: None,
         }),
+        semantic_tokens_provider: Some(
+            lsp_types::SemanticTokensServerCapabilities::SemanticTokensOptions(
+                lsp_types::SemanticTokensOptions {
+                    legend: semantic_tokens::legend(),
+                    full: Some(lsp_types::SemanticTokensFullOptions::Bool(true)),
+                    range: Some(true),
+                    ..Default::default()
+                },
+            ),
+        ),
+        code_lens_provider: Some(lsp_types::CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        code_action_provider: Some(lsp_types::CodeActionProviderCapability::Options(
+            lsp_types::CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    lsp_types::CodeActionKind::QUICKFIX,
+                    lsp_types::CodeActionKind::REFACTOR_REWRITE,
+                    lsp_types::CodeActionKind::REFACTOR_EXTRACT,
+                    lsp_types::CodeActionKind::new(code_actions::SORT_COMMANDS),
+                ]),
+                ..Default::default()
+            },
+        )),
+        folding_range_provider: Some(lsp_types::FoldingRangeProviderCapability::Simple(true)),
+        call_hierarchy_provider: Some(lsp_types::CallHierarchyServerCapability::Simple(true)),
+        document_highlight_provider: Some(lsp_types::OneOf::Left(true)),
+        selection_range_provider: Some(lsp_types::SelectionRangeProviderCapability::Simple(true)),
+        document_formatting_provider: Some(lsp_types::OneOf::Left(true)),
+        document_range_formatting_provider: Some(lsp_types::OneOf::Left(true)),
+        execute_command_provider: Some(lsp_types::ExecuteCommandOptions {
+            commands: vec![
+                runner::RUN_COMMAND.to_string(),
+                runner::RUN_WITH_ARGS_COMMAND.to_string(),
+                graph::GRAPH_COMMAND.to_string(),
+            ],
+            work_done_progress_options: lsp_types::WorkDoneProgressOptions {
+                work_done_progress: None,
+            },
+        }),
+        workspace: Some(lsp_types::WorkspaceServerCapabilities {
+            workspace_folders: Some(lsp_types::WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(lsp_types::OneOf::Left(true)),
+            }),
+            file_operations: None,
+        }),
         ..ServerCapabilities::default()
     };
 
@@ -59,27 +198,123 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
         "capabilities": server_capabilities,
         "serverInfo": {
             "name": "lets-ls",
-            "version": "0.1.0"
+            "version": cli::VERSION
         }
     });
 
     connection.initialize_finish(id, initialize_data)?;
 
+    let (folder_scans, scanned_folders) = crossbeam_channel::unbounded();
+    let (run_output, run_messages) = crossbeam_channel::unbounded();
+    let (lint_results, linted_documents) = crossbeam_channel::unbounded();
     let mut state = State::new();
+    state.folder_scans = Some(folder_scans);
+    state.lint_results = Some(lint_results);
+    state.run_output = Some(run_output);
+    for folder in params.workspace_folders.iter().flatten() {
+        state.add_workspace_folder(folder.uri.as_str());
+    }
+    if let Some(options) = params.initialization_options {
+        state.settings = Settings::from_options(&options);
+        state.initialization_options = options;
+    }
+    let workspace = params.capabilities.workspace.as_ref();
+    state.supports_configuration = workspace
+        .and_then(|workspace| workspace.configuration)
+        .unwrap_or(false);
+    let supports_configuration_registration = workspace
+        .and_then(|workspace| workspace.did_change_configuration.as_ref())
+        .and_then(|capabilities| capabilities.dynamic_registration)
+        .unwrap_or(false);
+    let supports_watched_files_registration = workspace
+        .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+        .and_then(|capabilities| capabilities.dynamic_registration)
+        .unwrap_or(false);
 
-    for msg in &connection.receiver {
+    logging::set_client(connection.sender.clone(), state.settings.log_level);
+    let mut tracer = Tracer::new(params.trace.unwrap_or(TraceValue::Off));
+
+    let initial = [
+        register_capabilities(
+            &mut state,
+            supports_configuration_registration,
+            supports_watched_files_registration,
+        ),
+        request_configuration(&mut state),
+    ];
+    for result in initial.into_iter().flatten() {
+        send_result(result, &connection, &state, &mut tracer)?;
+    }
+
+    loop {
+        let msg = crossbeam_channel::select! {
+            recv(connection.receiver) -> msg => match msg {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+            recv(scanned_folders) -> folder => {
+                let result = folder
+                    .ok()
+                    .and_then(|folder| handle_workspace_folder_scanned(folder, &mut state));
+                if let Some(result) = result {
+                    send_result(result, &connection, &state, &mut tracer)?;
+                }
+                continue;
+            }
+            recv(linted_documents) -> lint => {
+                let result = lint
+                    .ok()
+                    .and_then(|lint| handle_lint_finished(lint, &mut state));
+                if let Some(result) = result {
+                    send_result(result, &connection, &state, &mut tracer)?;
+                }
+                continue;
+            }
+            recv(run_messages) -> msg => {
+                if let Ok(msg) = msg {
+                    if let Some(trace) = tracer.outgoing(&msg) {
+                        connection.sender.send(trace)?;
+                    }
+                    connection.sender.send(msg)?;
+                }
+                continue;
+            }
+        };
+        if let Some(trace) = tracer.incoming(&msg) {
+            connection.sender.send(trace)?;
+        }
         let result: Option<LspResult> = match msg {
             Message::Request(req) => {
                 log::debug!("--> Request: {} {:?}", req.method, req.params);
                 match req.method.as_str() {
                     "textDocument/definition" => handle_definition(req, &mut state),
                     "textDocument/completion" => handle_completion(req, &mut state),
+                    "textDocument/hover" => handle_hover(req, &mut state),
+                    "textDocument/codeLens" => handle_code_lens(req, &mut state),
+                    "textDocument/codeAction" => handle_code_action(req, &mut state),
+                    "textDocument/foldingRange" => handle_folding_range(req, &mut state),
+                    "textDocument/documentHighlight" => handle_document_highlight(req, &mut state),
+                    "textDocument/prepareCallHierarchy" => {
+                        handle_prepare_call_hierarchy(req, &mut state)
+                    }
+                    "callHierarchy/incomingCalls" => handle_incoming_calls(req, &mut state),
+                    "callHierarchy/outgoingCalls" => handle_outgoing_calls(req, &mut state),
+                    "textDocument/selectionRange" => handle_selection_range(req, &mut state),
+                    "textDocument/formatting" => handle_formatting(req, &mut state),
+                    "textDocument/rangeFormatting" => handle_range_formatting(req, &mut state),
+                    "workspace/executeCommand" => handle_execute_command(req, &mut state),
+                    "textDocument/semanticTokens/full" => {
+                        handle_semantic_tokens_full(req, &mut state)
+                    }
+                    "textDocument/semanticTokens/range" => {
+                        handle_semantic_tokens_range(req, &mut state)
+                    }
                     "shutdown" => {
-                        connection.sender.send(
-                            Message::Response(lsp_server::Response::new_ok(req.id, ()))
-                        )?;
+                        state.runs.kill_all();
+                        // replies and waits for `exit` notification
+                        connection.handle_shutdown(&req)?;
                         break;
-                    },
+                    }
                     _ => None,
                 }
             }
@@ -88,28 +323,43 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
                 match notf.method.as_str() {
                     "textDocument/didOpen" => handle_didOpen(notf, &mut state),
                     "textDocument/didChange" => handle_didChange(notf, &mut state),
+                    "textDocument/didSave" => handle_did_save(notf, &mut state),
+                    "workspace/didChangeWorkspaceFolders" => {
+                        handle_did_change_workspace_folders(notf, &mut state)
+                    }
+                    "workspace/didChangeWatchedFiles" => {
+                        handle_did_change_watched_files(notf, &mut state)
+                    }
+                    "workspace/didChangeConfiguration" => {
+                        handle_did_change_configuration(notf, &mut state)
+                    }
+                    "$/setTrace" => {
+                        if let Ok(params) = serde_json::from_value::<SetTraceParams>(notf.params) {
+                            tracer.set(params.value);
+                        }
+                        None
+                    }
                     _ => None,
                 }
             }
-            _ => {
-                log::debug!("--> Other message type: {:?}", msg);
-                None
+            Message::Response(resp) => {
+                log::debug!("--> Response: {:?}", resp);
+                handle_response(resp, &mut state)
             }
         };
         log::debug!("<-- LspResult: {:?}", result);
         if let Some(result) = result {
-            match result {
-                LspResult::OK => (),
-                LspResult::Definition(result) => {
-                    connection.sender.send(definition_response(result)?)?
-                }
-                LspResult::Completion(result) => {
-                    connection.sender.send(completion_response(result))?
-                }
-            }
+            send_result(result, &connection, &state, &mut tracer)?;
         }
     }
 
+    // writer thread stops only when all senders are dropped,
+    // run threads still alive fail to send into the dropped channel
+    state.runs.kill_all();
+    logging::clear_client();
+    drop(run_messages);
+    drop(state);
+    drop(connection);
     io_threads.join()?;
 
     log::info!("Lets LSP server shutting down");
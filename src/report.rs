@@ -0,0 +1,306 @@
+use std::path::Path;
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+use serde_json::json;
+
+use crate::diagnostics::SOURCE;
+
+// Output format of `lets_ls check`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    Sarif,
+}
+
+// Diagnostics of a single file, the file is a path to print.
+pub type FileDiagnostics = (String, Vec<Diagnostic>);
+
+pub fn render(format: ReportFormat, files: &[FileDiagnostics]) -> String {
+    match format {
+        ReportFormat::Text => to_text(files),
+        ReportFormat::Json => to_json(files),
+        ReportFormat::Sarif => to_sarif(files),
+    }
+}
+
+pub fn is_error(diagnostic: &Diagnostic) -> bool {
+    matches!(diagnostic.severity, None | Some(DiagnosticSeverity::ERROR))
+}
+
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+fn code(diagnostic: &Diagnostic) -> Option<String> {
+    match diagnostic.code.as_ref()? {
+        NumberOrString::String(code) => Some(code.clone()),
+        NumberOrString::Number(code) => Some(code.to_string()),
+    }
+}
+
+// `file:line:col: severity: message` with 1-based line and column.
+fn format_diagnostic(file: &str, diagnostic: &Diagnostic) -> String {
+    format!(
+        "{}:{}:{}: {}: {}",
+        file,
+        diagnostic.range.start.line + 1,
+        diagnostic.range.start.character + 1,
+        severity_name(diagnostic.severity),
+        diagnostic.message
+    )
+}
+
+fn to_text(files: &[FileDiagnostics]) -> String {
+    files
+        .iter()
+        .flat_map(|(file, diagnostics)| {
+            diagnostics
+                .iter()
+                .map(|diagnostic| format_diagnostic(file, diagnostic) + "\n")
+        })
+        .collect()
+}
+
+// Lines and columns are 1-based as in the text output.
+fn to_json(files: &[FileDiagnostics]) -> String {
+    let diagnostics: Vec<serde_json::Value> = files
+        .iter()
+        .flat_map(|(file, diagnostics)| {
+            diagnostics.iter().map(move |diagnostic| {
+                let range = diagnostic.range;
+                json!({
+                    "file": file,
+                    "range": {
+                        "start": {"line": range.start.line + 1, "column": range.start.character + 1},
+                        "end": {"line": range.end.line + 1, "column": range.end.character + 1},
+                    },
+                    "severity": severity_name(diagnostic.severity),
+                    "code": code(diagnostic),
+                    "source": diagnostic.source,
+                    "message": diagnostic.message,
+                })
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&diagnostics).unwrap_or_default() + "\n"
+}
+
+// Base of relative artifact uris, the current directory.
+const SRCROOT: &str = "%SRCROOT%";
+
+// Relative paths are resolved against `SRCROOT`, other paths become `file://` uris.
+// root: uri of the current directory
+fn artifact_location(file: &str, root: Option<&url::Url>) -> serde_json::Value {
+    let path = Path::new(file);
+    let relative = root.filter(|_| path.is_relative()).and_then(|root| {
+        let uri = url::Url::from_file_path(root.to_file_path().ok()?.join(path)).ok()?;
+        root.make_relative(&uri)
+    });
+    if let Some(uri) = relative {
+        return json!({"uri": uri, "uriBaseId": SRCROOT});
+    }
+    match url::Url::from_file_path(path) {
+        Ok(uri) => json!({"uri": uri.as_str()}),
+        Err(_) => json!({"uri": file}),
+    }
+}
+
+// SARIF 2.1.0 log with a single run, see https://docs.oasis-open.org/sarif/sarif/v2.1.0/
+fn to_sarif(files: &[FileDiagnostics]) -> String {
+    let root = std::env::current_dir()
+        .ok()
+        .and_then(|dir| url::Url::from_directory_path(dir).ok());
+    let mut rules: Vec<String> = vec![];
+    let mut results = vec![];
+    for (file, diagnostics) in files {
+        for diagnostic in diagnostics {
+            let rule_id = code(diagnostic).unwrap_or_else(|| SOURCE.to_string());
+            if !rules.contains(&rule_id) {
+                rules.push(rule_id.clone());
+            }
+            let level = match diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) | Some(DiagnosticSeverity::HINT) => "note",
+                _ => "error",
+            };
+            let range = diagnostic.range;
+            results.push(json!({
+                "ruleId": rule_id,
+                "level": level,
+                "message": {"text": diagnostic.message},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": artifact_location(file, root.as_ref()),
+                        "region": {
+                            "startLine": range.start.line + 1,
+                            "startColumn": range.start.character + 1,
+                            "endLine": range.end.line + 1,
+                            "endColumn": range.end.character + 1,
+                        },
+                    },
+                }],
+            }));
+        }
+    }
+    let mut run = json!({
+        "tool": {
+            "driver": {
+                "name": SOURCE,
+                "version": std::env!("CARGO_PKG_VERSION"),
+                "rules": rules.iter().map(|id| json!({"id": id})).collect::<Vec<_>>(),
+            },
+        },
+        "results": results,
+    });
+    if let Some(root) = &root {
+        run["originalUriBaseIds"] = json!({SRCROOT: {"uri": root.as_str()}});
+    }
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [run],
+    });
+    serde_json::to_string_pretty(&log).unwrap_or_default() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::document_diagnostics;
+    use crate::graph::DependencyGraph;
+    use lsp_types::{Position, Range};
+
+    fn files() -> Vec<FileDiagnostics> {
+        vec![(
+            "lets.yaml".to_string(),
+            vec![
+                Diagnostic {
+                    range: Range::new(Position::new(3, 14), Position::new(3, 18)),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String("undefined-dependency".to_string())),
+                    source: Some(SOURCE.to_string()),
+                    message: "Command `buid` used in `depends` of `test` is not defined"
+                        .to_string(),
+                    ..Default::default()
+                },
+                Diagnostic {
+                    range: Range::new(Position::new(6, 9), Position::new(6, 13)),
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(NumberOrString::String("SC2086".to_string())),
+                    source: Some("shellcheck".to_string()),
+                    message: "Double quote to prevent globbing".to_string(),
+                    ..Default::default()
+                },
+            ],
+        )]
+    }
+
+    #[test]
+    fn test_render_text() {
+        assert_eq!(
+            render(ReportFormat::Text, &files()),
+            "lets.yaml:4:15: error: Command `buid` used in `depends` of `test` is not defined\n\
+             lets.yaml:7:10: info: Double quote to prevent globbing\n"
+        );
+    }
+
+    #[test]
+    fn test_render_json_and_sarif() {
+        let json: serde_json::Value =
+            serde_json::from_str(&render(ReportFormat::Json, &files())).unwrap();
+        assert_eq!(
+            json[0],
+            json!({
+                "file": "lets.yaml",
+                "range": {"start": {"line": 4, "column": 15}, "end": {"line": 4, "column": 19}},
+                "severity": "error",
+                "code": "undefined-dependency",
+                "source": "lets_ls",
+                "message": "Command `buid` used in `depends` of `test` is not defined",
+            })
+        );
+        assert_eq!(json[1]["code"], "SC2086");
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render(ReportFormat::Sarif, &files())).unwrap();
+        let run = &sarif["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([{"id": "undefined-dependency"}, {"id": "SC2086"}])
+        );
+        assert_eq!(run["results"][1]["level"], "note");
+        assert_eq!(
+            run["results"][0]["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": {"uri": "lets.yaml", "uriBaseId": "%SRCROOT%"},
+                "region": {"startLine": 4, "startColumn": 15, "endLine": 4, "endColumn": 19},
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_dependency_cycle() {
+        let doc = "commands:\n  build:\n    depends: [test]\n    cmd: echo\n  test:\n    depends: [build]\n    cmd: echo\n";
+        let graph = DependencyGraph::from_documents(&[(
+            "file:///project/lets.yaml".to_string(),
+            doc.to_string(),
+        )]);
+        let files = vec![(
+            "lets.yaml".to_string(),
+            document_diagnostics(doc, &graph, true, None),
+        )];
+
+        assert_eq!(
+            render(ReportFormat::Text, &files),
+            "lets.yaml:3:15: error: Command `test` in `depends` of `build` makes a dependency cycle: build -> test -> build\n\
+             lets.yaml:6:15: error: Command `build` in `depends` of `test` makes a dependency cycle: test -> build -> test\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(ReportFormat::Json, &files)).unwrap();
+        assert_eq!(json[0]["code"], "dependency-cycle");
+        assert_eq!(json[0]["severity"], "error");
+        assert_eq!(
+            json[1]["range"],
+            json!({"start": {"line": 6, "column": 15}, "end": {"line": 6, "column": 20}})
+        );
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render(ReportFormat::Sarif, &files)).unwrap();
+        let run = &sarif["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([{"id": "dependency-cycle"}])
+        );
+        assert_eq!(run["results"][0]["ruleId"], "dependency-cycle");
+        assert_eq!(run["results"][0]["level"], "error");
+    }
+
+    #[test]
+    fn test_sarif_artifact_location() {
+        let root = url::Url::from_directory_path("/project").unwrap();
+        assert_eq!(
+            artifact_location("my app/lets.yaml", Some(&root)),
+            json!({"uri": "my%20app/lets.yaml", "uriBaseId": "%SRCROOT%"})
+        );
+        assert_eq!(
+            artifact_location("/other/my app/lets.yaml", Some(&root)),
+            json!({"uri": "file:///other/my%20app/lets.yaml"})
+        );
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render(ReportFormat::Sarif, &files())).unwrap();
+        let cwd = url::Url::from_directory_path(std::env::current_dir().unwrap()).unwrap();
+        assert_eq!(
+            sarif["runs"][0]["originalUriBaseIds"]["%SRCROOT%"]["uri"],
+            cwd.as_str()
+        );
+    }
+}
@@ -42,3 +42,123 @@ pub fn completion_response(result: handler::CompletionResult) -> Message {
         error: None,
     })
 }
+
+pub fn hover_response(result: handler::HoverResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn semantic_tokens_response(result: handler::SemanticTokensResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn diagnostics_notification(result: handler::DiagnosticsResult) -> anyhow::Result<Message> {
+    let params = lsp_types::PublishDiagnosticsParams {
+        uri: result.uri.parse()?,
+        diagnostics: result.diagnostics,
+        version: None,
+    };
+    Ok(Message::Notification(lsp_server::Notification::new(
+        "textDocument/publishDiagnostics".to_string(),
+        params,
+    )))
+}
+
+pub fn client_request(result: handler::ClientRequestResult) -> Message {
+    Message::Request(result.request)
+}
+
+pub fn error_response(result: handler::ErrorResult) -> Message {
+    Message::Response(lsp_server::Response::new_err(
+        result.id,
+        result.code as i32,
+        result.message,
+    ))
+}
+
+pub fn code_lens_response(result: handler::CodeLensResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn code_action_response(result: handler::CodeActionResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn formatting_response(result: handler::FormattingResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn folding_range_response(result: handler::FoldingRangeResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn selection_range_response(result: handler::SelectionRangeResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn document_highlight_response(result: handler::DocumentHighlightResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn prepare_call_hierarchy_response(result: handler::PrepareCallHierarchyResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn incoming_calls_response(result: handler::IncomingCallsResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn outgoing_calls_response(result: handler::OutgoingCallsResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
+
+pub fn dependency_graph_response(result: handler::DependencyGraphResult) -> Message {
+    Message::Response(lsp_server::Response {
+        id: result.id,
+        result: serde_json::to_value(result.value).ok(),
+        error: None,
+    })
+}
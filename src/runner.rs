@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+
+use crossbeam_channel::Sender;
+use lsp_server::{Message, Notification, RequestId, Response};
+use lsp_types::{LogMessageParams, MessageType, ShowMessageParams};
+
+use crate::state::uri_to_path;
+
+pub const RUN_COMMAND: &str = "lets.run";
+pub const RUN_WITH_ARGS_COMMAND: &str = "lets.runWithArgs";
+
+// Command to run with lets: `lets <command> <args>` in work_dir.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RunRequest {
+    pub command: String,
+    pub args: Vec<String>,
+    pub work_dir: PathBuf,
+}
+
+impl RunRequest {
+    // Build request from `workspace/executeCommand` arguments:
+    // [uri of the config, command name, optional list of args]
+    pub fn from_arguments(arguments: &[serde_json::Value]) -> Option<Self> {
+        let uri = arguments.first()?.as_str()?;
+        let command = arguments.get(1)?.as_str()?;
+        let args = arguments
+            .get(2)
+            .and_then(|args| args.as_array())
+            .map(|args| {
+                args.iter()
+                    .filter_map(|arg| arg.as_str().map(|arg| arg.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let work_dir = uri_to_path(uri)?.parent()?.to_path_buf();
+        Some(Self {
+            command: command.to_string(),
+            args,
+            work_dir,
+        })
+    }
+}
+
+// Children of running commands by pid, they are killed when the server shuts down.
+#[derive(Debug, Clone, Default)]
+pub struct Runs(Arc<Mutex<HashMap<u32, Child>>>);
+
+impl Runs {
+    fn insert(&self, child: Child) -> u32 {
+        let pid = child.id();
+        self.0.lock().unwrap().insert(pid, child);
+        pid
+    }
+
+    fn remove(&self, pid: u32) -> Option<Child> {
+        self.0.lock().unwrap().remove(&pid)
+    }
+
+    // Kill running commands, their threads report them as terminated.
+    pub fn kill_all(&self) {
+        for child in self.0.lock().unwrap().values_mut() {
+            let _ = child.kill();
+        }
+    }
+}
+
+// Run the command and call on_line for each line of its stdout and stderr
+// as soon as it is printed.
+pub fn run(
+    lets_path: &str,
+    request: &RunRequest,
+    runs: &Runs,
+    mut on_line: impl FnMut(String),
+) -> anyhow::Result<ExitStatus> {
+    let mut child = Command::new(lets_path)
+        .arg(&request.command)
+        .args(&request.args)
+        .current_dir(&request.work_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let (tx, rx) = mpsc::channel();
+    let readers = [
+        forward_lines(child.stdout.take(), tx.clone()),
+        forward_lines(child.stderr.take(), tx),
+    ];
+    // output is read without holding the lock, so the child can be killed meanwhile
+    let pid = runs.insert(child);
+    for line in rx {
+        on_line(line);
+    }
+    for reader in readers.into_iter().flatten() {
+        let _ = reader.join();
+    }
+
+    let mut child = runs
+        .remove(pid)
+        .ok_or_else(|| anyhow::anyhow!("command {pid} is not tracked"))?;
+    Ok(child.wait()?)
+}
+
+fn forward_lines(
+    stream: Option<impl Read + Send + 'static>,
+    tx: mpsc::Sender<String>,
+) -> Option<std::thread::JoinHandle<()>> {
+    let stream = stream?;
+    Some(std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    }))
+}
+
+// Run the command, stream its output to the client with `window/logMessage`
+// and answer the `workspace/executeCommand` request once the command exits.
+pub fn run_and_report(
+    lets_path: &str,
+    id: RequestId,
+    request: RunRequest,
+    runs: &Runs,
+    sender: Sender<Message>,
+) {
+    let title = format!("lets {} {}", request.command, request.args.join(" "));
+    let title = title.trim_end();
+    let log = |typ: MessageType, message: String| {
+        let _ = sender.send(Message::Notification(Notification::new(
+            "window/logMessage".to_string(),
+            LogMessageParams { typ, message },
+        )));
+    };
+
+    log(MessageType::INFO, format!("$ {title}"));
+    let result = run(lets_path, &request, runs, |line| {
+        log(MessageType::LOG, line)
+    });
+
+    let (typ, message, response) = match result {
+        Ok(status) => {
+            let code = status.code();
+            let typ = if status.success() {
+                MessageType::INFO
+            } else {
+                MessageType::ERROR
+            };
+            let message = match code {
+                Some(code) => format!("{title} exited with code {code}"),
+                None => format!("{title} was terminated"),
+            };
+            let response = Response::new_ok(id, serde_json::json!({ "exitCode": code }));
+            (typ, message, response)
+        }
+        Err(err) => {
+            let message = format!("failed to run {title}: {err}");
+            let response = Response::new_err(
+                id,
+                lsp_server::ErrorCode::RequestFailed as i32,
+                message.clone(),
+            );
+            (MessageType::ERROR, message, response)
+        }
+    };
+
+    log(typ, message.clone());
+    let _ = sender.send(Message::Notification(Notification::new(
+        "window/showMessage".to_string(),
+        ShowMessageParams { typ, message },
+    )));
+    let _ = sender.send(Message::Response(response));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    // Create stub `lets` which prints its args and cwd and fails.
+    fn stub_lets(dir: &tempfile::TempDir) -> String {
+        let path = dir.path().join("lets");
+        std::fs::write(
+            &path,
+            "#!/bin/sh\necho \"args: $*\"\necho \"cwd: $(pwd)\"\necho oops >&2\nexit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_run_request_from_arguments() {
+        let arguments = vec![
+            serde_json::json!("file:///project/lets.yaml"),
+            serde_json::json!("test"),
+            serde_json::json!(["--force", "name"]),
+        ];
+        assert_eq!(
+            RunRequest::from_arguments(&arguments),
+            Some(RunRequest {
+                command: "test".to_string(),
+                args: vec!["--force".to_string(), "name".to_string()],
+                work_dir: PathBuf::from("/project"),
+            })
+        );
+        assert_eq!(RunRequest::from_arguments(&arguments[..1]), None);
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let lets = stub_lets(&dir);
+        let work_dir = dir.path().canonicalize().unwrap();
+        let request = RunRequest {
+            command: "test".to_string(),
+            args: vec!["--force".to_string()],
+            work_dir: work_dir.clone(),
+        };
+
+        let mut lines = vec![];
+        let status = run(&lets, &request, &Runs::default(), |line| lines.push(line)).unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "args: test --force".to_string(),
+                format!("cwd: {}", work_dir.display()),
+                "oops".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_and_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let lets = stub_lets(&dir);
+        let request = RunRequest {
+            command: "test".to_string(),
+            args: vec![],
+            work_dir: dir.path().to_path_buf(),
+        };
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        run_and_report(&lets, RequestId::from(1), request, &Runs::default(), sender);
+
+        let messages: Vec<Message> = receiver.try_iter().collect();
+        let Some(Message::Response(response)) = messages.last() else {
+            panic!("expected response, got {messages:?}");
+        };
+        assert_eq!(response.result, Some(serde_json::json!({"exitCode": 3})));
+        let Some(Message::Notification(notification)) = messages.get(messages.len() - 2) else {
+            panic!("expected notification, got {messages:?}");
+        };
+        assert_eq!(notification.method, "window/showMessage");
+        assert_eq!(
+            notification.params["message"],
+            "lets test exited with code 3"
+        );
+    }
+
+    #[test]
+    fn test_kill_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lets");
+        std::fs::write(&path, "#!/bin/sh\necho started\nexec sleep 30\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let lets = path.to_str().unwrap().to_string();
+        let request = RunRequest {
+            command: "test".to_string(),
+            args: vec![],
+            work_dir: dir.path().to_path_buf(),
+        };
+        let runs = Runs::default();
+
+        let (started, wait_started) = mpsc::channel();
+        let thread = {
+            let runs = runs.clone();
+            std::thread::spawn(move || {
+                run(&lets, &request, &runs, |_| {
+                    let _ = started.send(());
+                })
+            })
+        };
+        wait_started.recv().unwrap();
+        runs.kill_all();
+
+        let status = thread.join().unwrap().unwrap();
+        assert_eq!(status.code(), None);
+        assert!(runs.0.lock().unwrap().is_empty());
+    }
+}
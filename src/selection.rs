@@ -0,0 +1,105 @@
+use lsp_types::{Position, Range, SelectionRange};
+use tree_sitter::{Node, Point};
+
+use crate::treesitter::{last_row, node_range, Parser};
+
+// Range of the node without the line break block values end with.
+fn trimmed_range(node: &Node, lines: &[&str]) -> Range {
+    let mut range = node_range(node);
+    let row = last_row(node);
+    if row < node.end_position().row {
+        let len = lines.get(row).map_or(0, |line| line.len());
+        range.end = Position::new(row as u32, len as u32);
+    }
+    range
+}
+
+// Selection ranges from the smallest node at each position up to the whole document,
+// e.g. scalar -> `depends` item -> `depends` list -> pair -> command -> `commands`.
+pub fn selection_ranges(doc: &str, positions: &[Position]) -> Vec<SelectionRange> {
+    let tree = Parser::new().parse(doc);
+    let lines: Vec<&str> = doc.lines().collect();
+
+    positions
+        .iter()
+        .map(|position| {
+            let point = Point::new(position.line as usize, position.character as usize);
+            let node = tree
+                .root_node()
+                .named_descendant_for_point_range(point, point)
+                .unwrap_or(tree.root_node());
+
+            let mut ranges: Vec<Range> = vec![];
+            for node in std::iter::successors(Some(node), |node| node.parent()) {
+                let range = trimmed_range(&node, &lines);
+                if ranges.last() != Some(&range) {
+                    ranges.push(range);
+                }
+            }
+
+            // build the chain from the outermost range
+            let mut selection: Option<SelectionRange> = None;
+            for range in ranges.into_iter().rev() {
+                selection = Some(SelectionRange {
+                    range,
+                    parent: selection.map(Box::new),
+                });
+            }
+            selection.unwrap_or(SelectionRange {
+                range: Range::new(*position, *position),
+                parent: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_ranges() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    depends:
+      - build
+      - lint
+    cmd: echo Test"#
+            .trim();
+
+        let selection = &selection_ranges(doc, &[Position::new(4, 9)])[0];
+        let mut ranges = vec![];
+        let mut current = Some(selection);
+        while let Some(selection) = current {
+            let range = selection.range;
+            ranges.push((
+                (range.start.line, range.start.character),
+                (range.end.line, range.end.character),
+            ));
+            current = selection.parent.as_deref();
+        }
+        assert_eq!(
+            ranges,
+            vec![
+                // build
+                ((4, 8), (4, 13)),
+                // - build
+                ((4, 6), (4, 13)),
+                // depends list
+                ((4, 6), (5, 12)),
+                // depends pair
+                ((3, 4), (5, 12)),
+                // command body
+                ((3, 4), (6, 18)),
+                // test command
+                ((2, 2), (6, 18)),
+                // commands
+                ((1, 0), (6, 18)),
+                // document
+                ((0, 0), (6, 18)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use lsp_types::{
+    Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+};
+use tree_sitter::Node;
+
+use crate::builtins::{COMMAND_KEYWORDS, DEPRECATED_KEYWORDS, ROOT_KEYWORDS};
+use crate::shell::Script;
+use crate::treesitter::{
+    find_root_mapping, get_node_text, get_pair_key, get_sequence_items, node_range, Parser,
+};
+
+// Indexes in the legend token types.
+const COMMAND: u32 = 0;
+const KEYWORD: u32 = 1;
+const MIXIN: u32 = 2;
+const VARIABLE: u32 = 3;
+const DOCOPT_ARGUMENT: u32 = 4;
+const DOCOPT_OPTION: u32 = 5;
+const COMMENT: u32 = 6;
+
+// Bit flags of the legend token modifiers.
+const DECLARATION: u32 = 1;
+const DEPRECATED: u32 = 1 << 1;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::NAMESPACE,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::COMMENT,
+        ],
+        token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::DEPRECATED,
+        ],
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Token {
+    line: u32,
+    start: u32,
+    length: u32,
+    token_type: u32,
+    modifiers: u32,
+}
+
+// Collect semantic tokens for the document, optionally limited to lines in range.
+pub fn semantic_tokens(doc: &str, range: Option<Range>) -> Vec<SemanticToken> {
+    let mut tokens = collect_tokens(doc);
+    if let Some(range) = range {
+        tokens.retain(|t| t.line >= range.start.line && t.line <= range.end.line);
+    }
+    encode(tokens)
+}
+
+fn collect_tokens(doc: &str) -> Vec<Token> {
+    let parser = Parser::new();
+    let tree = parser.parse(doc);
+    let mut tokens = vec![];
+    let Some(root) = find_root_mapping(&tree.root_node()) else {
+        return tokens;
+    };
+
+    let mut cursor = root.walk();
+    for pair in root.named_children(&mut cursor) {
+        let Some(key) = get_pair_key(&pair, doc) else {
+            continue;
+        };
+        let key_node = pair.child_by_field_name("key").unwrap();
+        if ROOT_KEYWORDS.contains(&key) {
+            push_keyword(&mut tokens, &key_node, key);
+        }
+        let Some(value) = pair.child_by_field_name("value") else {
+            continue;
+        };
+        match key {
+            "mixins" => {
+                for item in get_sequence_items(&value) {
+                    push_node(&mut tokens, &item, MIXIN, 0);
+                }
+            }
+            "env" | "eval_env" => push_env_declarations(&mut tokens, &value, doc),
+            "commands" => push_commands(&mut tokens, &value, doc),
+            _ => {}
+        }
+    }
+
+    for reference in parser.get_command_references(doc) {
+        push_range(&mut tokens, &reference.range, COMMAND, 0);
+    }
+    for script in parser.get_scripts(doc) {
+        push_script(&mut tokens, &script);
+    }
+
+    tokens.sort_by_key(|t| (t.line, t.start));
+    tokens
+}
+
+fn push_commands(tokens: &mut Vec<Token>, commands: &Node, doc: &str) {
+    let Some(mapping) = commands.named_child(0) else {
+        return;
+    };
+    let mut cursor = mapping.walk();
+    for command in mapping.named_children(&mut cursor) {
+        let Some(key) = command.child_by_field_name("key") else {
+            continue;
+        };
+        push_node(tokens, &key, COMMAND, DECLARATION);
+
+        let Some(body) = command
+            .child_by_field_name("value")
+            .and_then(|value| value.named_child(0))
+            .filter(|body| body.kind() == "block_mapping")
+        else {
+            continue;
+        };
+
+        let mut cursor = body.walk();
+        for pair in body.named_children(&mut cursor) {
+            let (Some(key), Some(key_node)) =
+                (get_pair_key(&pair, doc), pair.child_by_field_name("key"))
+            else {
+                continue;
+            };
+            if COMMAND_KEYWORDS.contains(&key) {
+                push_keyword(tokens, &key_node, key);
+            }
+            let Some(value) = pair.child_by_field_name("value") else {
+                continue;
+            };
+            match key {
+                "env" | "eval_env" => push_env_declarations(tokens, &value, doc),
+                "options" => push_docopt(tokens, &value, doc),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn push_env_declarations(tokens: &mut Vec<Token>, value: &Node, doc: &str) {
+    let Some(mapping) = value.named_child(0) else {
+        return;
+    };
+    let mut cursor = mapping.walk();
+    for pair in mapping.named_children(&mut cursor) {
+        if let Some(key) = pair.child_by_field_name("key") {
+            push_node(tokens, &key, VARIABLE, DECLARATION);
+        }
+        // `sh:` and `checksum:` value forms
+        let Some(inner) = pair
+            .child_by_field_name("value")
+            .and_then(|value| value.named_child(0))
+            .filter(|inner| matches!(inner.kind(), "block_mapping" | "flow_mapping"))
+        else {
+            continue;
+        };
+        let mut cursor = inner.walk();
+        for pair in inner.named_children(&mut cursor) {
+            if let (Some(key), Some(key_node)) =
+                (get_pair_key(&pair, doc), pair.child_by_field_name("key"))
+            {
+                if key == "sh" || key == "checksum" {
+                    push_keyword(tokens, &key_node, key);
+                }
+            }
+        }
+    }
+}
+
+fn push_script(tokens: &mut Vec<Token>, script: &Script) {
+    let refs = script
+        .variable_references()
+        .into_iter()
+        .map(|var| var.range);
+    let vars = refs.map(|range| (range, VARIABLE));
+    let comments = script.comments().into_iter().map(|range| (range, COMMENT));
+    for (range, token_type) in vars.chain(comments) {
+        if range.start.line != range.end.line {
+            continue;
+        }
+        tokens.push(Token {
+            line: range.start.line,
+            start: range.start.character,
+            length: range.end.character - range.start.character,
+            token_type,
+            modifiers: 0,
+        });
+    }
+}
+
+fn push_docopt(tokens: &mut Vec<Token>, value: &Node, doc: &str) {
+    for_each_line(value, doc, |line, offset, text| {
+        for (start, word) in docopt_words(text) {
+            let token_type = if word.starts_with('<') && word.ends_with('>') {
+                DOCOPT_ARGUMENT
+            } else if word.starts_with('-') && word.len() > 1 && word != "--" {
+                DOCOPT_OPTION
+            } else if word.ends_with(':') && text[..start].trim().is_empty() {
+                KEYWORD
+            } else {
+                continue;
+            };
+            tokens.push(Token {
+                line,
+                start: (offset + start) as u32,
+                length: word.len() as u32,
+                token_type,
+                modifiers: 0,
+            });
+        }
+    });
+}
+
+// Split docopt line into words with their byte offsets.
+// For option description lines only the option names are returned.
+fn docopt_words(line: &str) -> Vec<(usize, &str)> {
+    let is_option_line = line.trim_start().starts_with('-');
+    let end = if is_option_line {
+        let indent = line.len() - line.trim_start().len();
+        line[indent..]
+            .find("  ")
+            .map_or(line.len(), |idx| indent + idx)
+    } else {
+        line.len()
+    };
+
+    let mut words = vec![];
+    let mut start = None;
+    for (idx, c) in line[..end].char_indices() {
+        let is_separator = c.is_whitespace() || "[]()|,=.".contains(c);
+        match (start, is_separator) {
+            (None, false) => start = Some(idx),
+            (Some(s), true) => {
+                words.push((s, &line[s..idx]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, &line[s..end]));
+    }
+    words
+}
+
+// Call f with row, column offset and text of each line of the node.
+fn for_each_line(node: &Node, doc: &str, mut f: impl FnMut(u32, usize, &str)) {
+    let Some(text) = get_node_text(node, doc) else {
+        return;
+    };
+    let start = node.start_position();
+    for (i, line) in text.lines().enumerate() {
+        let offset = if i == 0 { start.column } else { 0 };
+        f((start.row + i) as u32, offset, line);
+    }
+}
+
+fn push_keyword(tokens: &mut Vec<Token>, node: &Node, key: &str) {
+    let modifiers = if DEPRECATED_KEYWORDS.contains(&key) {
+        DEPRECATED
+    } else {
+        0
+    };
+    push_node(tokens, node, KEYWORD, modifiers);
+}
+
+fn push_node(tokens: &mut Vec<Token>, node: &Node, token_type: u32, modifiers: u32) {
+    push_range(tokens, &node_range(node), token_type, modifiers);
+}
+
+fn push_range(tokens: &mut Vec<Token>, range: &Range, token_type: u32, modifiers: u32) {
+    // semantic tokens can not span multiple lines
+    if range.start.line != range.end.line || range.start.character == range.end.character {
+        return;
+    }
+    tokens.push(Token {
+        line: range.start.line,
+        start: range.start.character,
+        length: range.end.character - range.start.character,
+        token_type,
+        modifiers,
+    });
+}
+
+// Encode sorted tokens relative to the previous one as LSP expects.
+fn encode(tokens: Vec<Token>) -> Vec<SemanticToken> {
+    let mut prev_line = 0;
+    let mut prev_start = 0;
+    tokens
+        .into_iter()
+        .map(|t| {
+            let delta_line = t.line - prev_line;
+            let delta_start = if delta_line == 0 {
+                t.start - prev_start
+            } else {
+                t.start
+            };
+            prev_line = t.line;
+            prev_start = t.start;
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: t.length,
+                token_type: t.token_type,
+                token_modifiers_bitset: t.modifiers,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+
+    fn token(line: u32, start: u32, length: u32, token_type: u32, modifiers: u32) -> Token {
+        Token {
+            line,
+            start,
+            length,
+            token_type,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn test_collect_tokens() {
+        let doc = r#"
+mixins:
+  - lets.my.yaml
+eval_env:
+  FOO: echo foo
+commands:
+  test:
+    depends: [build]
+    options: |
+      Usage: lets test [--force] <name>
+    cmd: |
+      # $FOO
+      echo $FOO"#
+            .trim();
+
+        assert_eq!(
+            collect_tokens(doc),
+            vec![
+                token(0, 0, 6, KEYWORD, 0),
+                token(1, 4, 12, MIXIN, 0),
+                token(2, 0, 8, KEYWORD, DEPRECATED),
+                token(3, 2, 3, VARIABLE, DECLARATION),
+                token(4, 0, 8, KEYWORD, 0),
+                token(5, 2, 4, COMMAND, DECLARATION),
+                token(6, 4, 7, KEYWORD, 0),
+                token(6, 14, 5, COMMAND, 0),
+                token(7, 4, 7, KEYWORD, 0),
+                token(8, 6, 6, KEYWORD, 0),
+                token(8, 24, 7, DOCOPT_OPTION, 0),
+                token(8, 33, 6, DOCOPT_ARGUMENT, 0),
+                token(9, 4, 3, KEYWORD, 0),
+                token(10, 6, 6, COMMENT, 0),
+                token(11, 12, 3, VARIABLE, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_depends_mapping_items() {
+        let doc = r#"
+commands:
+  test:
+    depends:
+      - name: build
+        args: --release"#
+            .trim();
+
+        let tokens = collect_tokens(doc);
+        assert!(tokens.contains(&token(3, 14, 5, COMMAND, 0)));
+    }
+
+    #[test]
+    fn test_docopt_option_lines() {
+        let words = docopt_words("  -f, --force  Force [default: -x]");
+        assert_eq!(words, vec![(2, "-f"), (6, "--force")]);
+    }
+
+    #[test]
+    fn test_semantic_tokens_range() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    cmd: echo Test"#
+            .trim();
+
+        let range = Range::new(Position::new(2, 0), Position::new(3, 0));
+        let tokens = semantic_tokens(doc, Some(range));
+        assert_eq!(
+            tokens,
+            vec![
+                SemanticToken {
+                    delta_line: 2,
+                    delta_start: 2,
+                    length: 4,
+                    token_type: COMMAND,
+                    token_modifiers_bitset: DECLARATION,
+                },
+                SemanticToken {
+                    delta_line: 1,
+                    delta_start: 4,
+                    length: 3,
+                    token_type: KEYWORD,
+                    token_modifiers_bitset: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use log::LevelFilter;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::diagnostics::ExternalLinter;
+use crate::formatting::FormatOptions;
+use crate::logging;
+
+// Section of the server in client settings, e.g. `settings = { lets_ls = { ... } }`.
+pub const SECTION: &str = "lets_ls";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiagnosticsSettings {
+    // codes of diagnostics which are not reported
+    pub disabled: Vec<String>,
+    // severity of diagnostics by code
+    pub severity: HashMap<String, DiagnosticSeverity>,
+}
+
+// Settings of the server, read from initialization options or client configuration:
+// {
+//   "lets": { "path": "lets" },
+//   "linter": { "path": "shellcheck", "args": ["-f", "json", "-"] },
+//   "format": { "keyOrder": ["description", "cmd"] },
+//   "diagnostics": { "disabled": ["SC2086"], "severity": { "undefined-dependency": "warning" } },
+//   "log": { "level": "info" }
+// }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    // path to lets executable used to run commands
+    pub lets_path: String,
+    pub linter: Option<ExternalLinter>,
+    pub format: FormatOptions,
+    pub diagnostics: DiagnosticsSettings,
+    // level of logs sent to the client
+    pub log_level: LevelFilter,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            lets_path: "lets".to_string(),
+            linter: None,
+            format: FormatOptions::default(),
+            diagnostics: DiagnosticsSettings::default(),
+            log_level: logging::DEFAULT_CLIENT_LEVEL,
+        }
+    }
+}
+
+fn parse_severity(name: &str) -> Option<DiagnosticSeverity> {
+    match name {
+        "error" => Some(DiagnosticSeverity::ERROR),
+        "warning" => Some(DiagnosticSeverity::WARNING),
+        "info" | "information" => Some(DiagnosticSeverity::INFORMATION),
+        "hint" => Some(DiagnosticSeverity::HINT),
+        _ => None,
+    }
+}
+
+impl DiagnosticsSettings {
+    fn from_options(options: &serde_json::Value) -> Self {
+        let mut result = Self::default();
+        if let Some(disabled) = options
+            .pointer("/diagnostics/disabled")
+            .and_then(|disabled| disabled.as_array())
+        {
+            result.disabled = disabled
+                .iter()
+                .filter_map(|code| code.as_str().map(|code| code.to_string()))
+                .collect();
+        }
+        if let Some(severity) = options
+            .pointer("/diagnostics/severity")
+            .and_then(|severity| severity.as_object())
+        {
+            result.severity = severity
+                .iter()
+                .filter_map(|(code, name)| Some((code.clone(), parse_severity(name.as_str()?)?)))
+                .collect();
+        }
+        result
+    }
+
+    // Drop disabled diagnostics and override severity of the rest.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                let code = match &diagnostic.code {
+                    Some(NumberOrString::String(code)) => code.clone(),
+                    Some(NumberOrString::Number(code)) => code.to_string(),
+                    None => return Some(diagnostic),
+                };
+                if self.disabled.contains(&code) {
+                    return None;
+                }
+                if let Some(severity) = self.severity.get(&code) {
+                    diagnostic.severity = Some(*severity);
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
+
+// Settings may be nested in the server section, e.g. in `workspace/didChangeConfiguration`.
+fn section(options: &serde_json::Value) -> &serde_json::Value {
+    options.get(SECTION).unwrap_or(options)
+}
+
+// Merge client settings over initialization options, nested objects are merged
+// and missing or null values keep the base ones.
+pub fn merge_options(base: &serde_json::Value, options: &serde_json::Value) -> serde_json::Value {
+    match (section(base), section(options)) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(options)) => {
+            let mut result = base.clone();
+            for (key, value) in options {
+                let merged = match result.get(key) {
+                    Some(base) => merge_options(base, value),
+                    None => value.clone(),
+                };
+                result.insert(key.clone(), merged);
+            }
+            serde_json::Value::Object(result)
+        }
+        (base, serde_json::Value::Null) => base.clone(),
+        (_, options) => options.clone(),
+    }
+}
+
+impl Settings {
+    pub fn from_options(options: &serde_json::Value) -> Self {
+        let options = section(options);
+        Self {
+            lets_path: options
+                .pointer("/lets/path")
+                .and_then(|path| path.as_str())
+                .unwrap_or("lets")
+                .to_string(),
+            linter: ExternalLinter::from_options(options),
+            format: FormatOptions::from_options(options),
+            diagnostics: DiagnosticsSettings::from_options(options),
+            log_level: logging::level_from_options(options)
+                .unwrap_or(logging::DEFAULT_CLIENT_LEVEL),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_from_options() {
+        let options = serde_json::json!({
+            "lets_ls": {
+                "lets": {"path": "/usr/local/bin/lets"},
+                "linter": {"path": "shellcheck"},
+                "format": {"keyOrder": ["cmd"]},
+                "diagnostics": {
+                    "disabled": ["SC2086"],
+                    "severity": {"undefined-dependency": "warning", "SC2034": "loud"}
+                },
+                "log": {"level": "debug"}
+            }
+        });
+        let settings = Settings::from_options(&options);
+        assert_eq!(settings.lets_path, "/usr/local/bin/lets");
+        assert_eq!(settings.linter, Some(ExternalLinter::new("shellcheck")));
+        assert_eq!(settings.format.key_order, vec!["cmd"]);
+        assert_eq!(settings.diagnostics.disabled, vec!["SC2086"]);
+        assert_eq!(
+            settings.diagnostics.severity,
+            HashMap::from([(
+                "undefined-dependency".to_string(),
+                DiagnosticSeverity::WARNING
+            )])
+        );
+        assert_eq!(settings.log_level, LevelFilter::Debug);
+
+        assert_eq!(
+            Settings::from_options(&serde_json::Value::Null),
+            Settings::default()
+        );
+
+        let diagnostic = |code: &str| Diagnostic {
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(code.to_string())),
+            ..Default::default()
+        };
+        let diagnostics = settings.diagnostics.apply(vec![
+            diagnostic("undefined-dependency"),
+            diagnostic("SC2086"),
+            diagnostic("SC2034"),
+        ]);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.code.clone().unwrap(), d.severity.unwrap()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    NumberOrString::String("undefined-dependency".to_string()),
+                    DiagnosticSeverity::WARNING
+                ),
+                (
+                    NumberOrString::String("SC2034".to_string()),
+                    DiagnosticSeverity::ERROR
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_options() {
+        let base = serde_json::json!({
+            "lets": {"path": "/usr/local/bin/lets"},
+            "diagnostics": {"disabled": ["SC2086"], "severity": {"SC2034": "hint"}}
+        });
+        assert_eq!(merge_options(&base, &serde_json::json!({})), base);
+        assert_eq!(merge_options(&base, &serde_json::Value::Null), base);
+        assert_eq!(
+            merge_options(
+                &base,
+                &serde_json::json!({"lets_ls": {
+                    "diagnostics": {"disabled": [], "severity": {"undefined-dependency": "warning"}},
+                    "log": {"level": "debug"}
+                }})
+            ),
+            serde_json::json!({
+                "lets": {"path": "/usr/local/bin/lets"},
+                "diagnostics": {
+                    "disabled": [],
+                    "severity": {"SC2034": "hint", "undefined-dependency": "warning"}
+                },
+                "log": {"level": "debug"}
+            })
+        );
+    }
+}
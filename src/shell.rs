@@ -0,0 +1,156 @@
+use lsp_types::{Position, Range};
+use tree_sitter::{Node, Point, Tree};
+
+// Shell script embedded into a YAML value (`cmd`, `before`, `sh:`, etc).
+// The script is parsed with the bash grammar and positions in the script
+// are mapped back to the YAML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    // YAML key the script belongs to: `cmd`, `after`, `before`, `init`, `sh` or `eval_env`
+    pub key: &'static str,
+    pub text: String,
+    // range of the YAML value in the document
+    pub range: Range,
+    // document position of the first character of each script line
+    lines: Vec<Position>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VariableReference {
+    pub name: String,
+    // range of the variable name in the document
+    pub range: Range,
+}
+
+impl Script {
+    pub fn new(key: &'static str, text: String, range: Range, lines: Vec<Position>) -> Self {
+        Self {
+            key,
+            text,
+            range,
+            lines,
+        }
+    }
+
+    pub fn parse(&self) -> Tree {
+        let mut parser = tree_sitter::Parser::new();
+        let language = tree_sitter_bash::LANGUAGE.into();
+        parser
+            .set_language(&language)
+            .expect("could not load bash language");
+        parser
+            .parse(&self.text, None)
+            .expect("could not parse script")
+    }
+
+    pub fn lines(&self) -> &[Position] {
+        &self.lines
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        self.range.start <= *pos && *pos <= self.range.end
+    }
+
+    pub fn to_document_position(&self, point: Point) -> Position {
+        let Some(start) = self.lines.get(point.row) else {
+            return self.range.end;
+        };
+        Position::new(start.line, start.character + point.column as u32)
+    }
+
+    pub fn to_document_range(&self, node: &Node) -> Range {
+        Range::new(
+            self.to_document_position(node.start_position()),
+            self.to_document_position(node.end_position()),
+        )
+    }
+
+    // Return `$NAME` and `${NAME}` references, skipping positional and special parameters.
+    pub fn variable_references(&self) -> Vec<VariableReference> {
+        let tree = self.parse();
+        let mut refs = vec![];
+        walk(&tree.root_node(), &mut |node| {
+            if node.kind() != "variable_name" {
+                return;
+            }
+            let is_expansion = node
+                .parent()
+                .is_some_and(|p| matches!(p.kind(), "simple_expansion" | "expansion"));
+            let name = &self.text[node.byte_range()];
+            if is_expansion && !name.starts_with(|c: char| c.is_ascii_digit()) {
+                refs.push(VariableReference {
+                    name: name.to_string(),
+                    range: self.to_document_range(node),
+                });
+            }
+        });
+        refs
+    }
+
+    pub fn comments(&self) -> Vec<Range> {
+        let tree = self.parse();
+        let mut comments = vec![];
+        walk(&tree.root_node(), &mut |node| {
+            if node.kind() == "comment" {
+                comments.push(self.to_document_range(node));
+            }
+        });
+        comments
+    }
+}
+
+fn walk<'t>(node: &Node<'t>, f: &mut impl FnMut(&Node<'t>)) {
+    f(node);
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk(&child, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_script() -> Script {
+        // cmd: |
+        //   echo $FOO '$NOPE'
+        //   # $COMMENT
+        //   echo ${BAR:-x} $1
+        Script::new(
+            "cmd",
+            "echo $FOO '$NOPE'\n# $COMMENT\necho ${BAR:-x} $1".to_string(),
+            Range::new(Position::new(3, 9), Position::new(6, 0)),
+            vec![
+                Position::new(4, 6),
+                Position::new(5, 6),
+                Position::new(6, 6),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_variable_references() {
+        let refs = block_script().variable_references();
+        assert_eq!(
+            refs,
+            vec![
+                VariableReference {
+                    name: "FOO".to_string(),
+                    range: Range::new(Position::new(4, 12), Position::new(4, 15)),
+                },
+                VariableReference {
+                    name: "BAR".to_string(),
+                    range: Range::new(Position::new(6, 13), Position::new(6, 16)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(
+            block_script().comments(),
+            vec![Range::new(Position::new(5, 6), Position::new(5, 16))]
+        );
+    }
+}
@@ -1,25 +1,594 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crossbeam_channel::Sender;
+use lsp_server::{Message, RequestId};
+use lsp_types::Diagnostic;
+
+use crate::runner::Runs;
+use crate::settings::Settings;
+use crate::treesitter::Parser;
+
+// File name of the main lets config.
+const ROOT_CONFIG: &str = "lets.yaml";
+// How deep root configs are searched for in workspace folders.
+const MAX_SEARCH_DEPTH: usize = 8;
+// Directories with dependencies or build artifacts, not searched for root configs.
+const SKIPPED_DIRS: [&str; 6] = [
+    "node_modules",
+    "target",
+    "vendor",
+    "dist",
+    "venv",
+    "__pycache__",
+];
+
+// Workspace folder opened in the client and root configs found in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceFolder {
+    pub(crate) uri: String,
+    pub(crate) configs: Vec<String>,
+}
+
+// Findings of the external linter for a document, see `State::lint_results`.
+#[derive(Debug)]
+pub struct LintResult {
+    pub(crate) uri: String,
+    pub(crate) id: u64,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
 
 pub struct State {
     pub(crate) documents: HashMap<String, String>,
+    pub(crate) workspace_folders: Vec<WorkspaceFolder>,
+    pub(crate) settings: Settings,
+    // initialization options, client settings are merged over them
+    pub(crate) initialization_options: serde_json::Value,
+    // findings of the external linter by document, updated on open and save
+    pub(crate) lint_diagnostics: HashMap<String, Vec<Diagnostic>>,
+    // documents are linted in background and results are sent here if set,
+    // otherwise they are linted in place
+    pub(crate) lint_results: Option<Sender<LintResult>>,
+    // id of the latest lint of each document, results of earlier ones are dropped
+    pub(crate) pending_lints: HashMap<String, u64>,
+    last_lint_id: u64,
+    // client supports `workspace/configuration` requests
+    pub(crate) supports_configuration: bool,
+    // id of `workspace/configuration` request waiting for the response
+    pub(crate) configuration_request: Option<RequestId>,
+    // uris of configs and their mixins by config, cleared when mixins may change
+    config_uris: RefCell<HashMap<String, Vec<String>>>,
+    // workspace folders are scanned in background and sent here if set,
+    // otherwise they are scanned in place
+    pub(crate) folder_scans: Option<Sender<WorkspaceFolder>>,
+    // commands started with `lets.run`
+    pub(crate) runs: Runs,
+    // output of running commands is sent here and forwarded to the client by the main loop,
+    // so it stops when the loop ends
+    pub(crate) run_output: Option<Sender<Message>>,
+    last_request_id: i32,
 }
 
 impl State {
     pub(crate) fn new() -> Self {
         Self {
             documents: HashMap::new(),
+            workspace_folders: vec![],
+            settings: Settings::default(),
+            initialization_options: serde_json::Value::Null,
+            lint_diagnostics: HashMap::new(),
+            lint_results: None,
+            pending_lints: HashMap::new(),
+            last_lint_id: 0,
+            supports_configuration: false,
+            configuration_request: None,
+            config_uris: RefCell::new(HashMap::new()),
+            folder_scans: None,
+            runs: Runs::default(),
+            run_output: None,
+            last_request_id: 0,
         }
     }
 
+    // Id for a request sent from the server to the client.
+    pub(crate) fn next_request_id(&mut self) -> RequestId {
+        self.last_request_id += 1;
+        RequestId::from(self.last_request_id)
+    }
+
+    // Id for a background lint of a document.
+    pub(crate) fn next_lint_id(&mut self) -> u64 {
+        self.last_lint_id += 1;
+        self.last_lint_id
+    }
+
+    // Opened document may differ from the file on disk.
     pub(crate) fn add_document(&mut self, name: String, doc: String) {
+        self.config_uris.get_mut().clear();
         self.documents.insert(name, doc);
     }
 
+    // Most changes do not touch `mixins`, so cached config uris are kept for them.
     pub(crate) fn update_document(&mut self, name: String, doc: String) {
+        let parser = Parser::new();
+        let mixins_changed = self
+            .documents
+            .get(&name)
+            .is_none_or(|old| parser.get_mixins(old) != parser.get_mixins(&doc));
+        if mixins_changed {
+            self.config_uris.get_mut().clear();
+        }
         self.documents.insert(name, doc);
     }
 
     pub(crate) fn get_document(&self, name: &str) -> Option<&String> {
         self.documents.get(name)
     }
+
+    // Return opened document or read it from disk.
+    pub(crate) fn read_document(&self, uri: &str) -> Option<String> {
+        let doc = self.get_document(uri).or_else(|| {
+            self.documents
+                .iter()
+                .find(|(name, _)| same_file(name, uri))
+                .map(|(_, doc)| doc)
+        });
+        if let Some(doc) = doc {
+            return Some(doc.clone());
+        }
+        std::fs::read_to_string(uri_to_path(uri)?).ok()
+    }
+
+    // Root configs are searched for in background if `folder_scans` is set,
+    // the folder has no configs until the scan is passed to `set_workspace_folder`.
+    pub(crate) fn add_workspace_folder(&mut self, uri: &str) {
+        let uri = uri.trim_end_matches('/').to_string();
+        self.remove_workspace_folder(&uri);
+        match &self.folder_scans {
+            Some(sender) => {
+                let sender = sender.clone();
+                let scanned_uri = uri.clone();
+                std::thread::spawn(move || {
+                    let _ = sender.send(scan_workspace_folder(&scanned_uri));
+                });
+                self.workspace_folders.push(WorkspaceFolder {
+                    uri,
+                    configs: vec![],
+                });
+            }
+            None => self.workspace_folders.push(scan_workspace_folder(&uri)),
+        }
+    }
+
+    // Use configs of the scanned folder, return false if the folder was removed meanwhile.
+    pub(crate) fn set_workspace_folder(&mut self, scanned: WorkspaceFolder) -> bool {
+        match self
+            .workspace_folders
+            .iter_mut()
+            .find(|folder| folder.uri == scanned.uri)
+        {
+            Some(folder) => {
+                *folder = scanned;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn remove_workspace_folder(&mut self, uri: &str) {
+        self.workspace_folders
+            .retain(|folder| !same_file(&folder.uri, uri));
+    }
+
+    // Innermost workspace folder which contains the file.
+    fn workspace_folder(&self, uri: &str) -> Option<&WorkspaceFolder> {
+        let path = uri_to_path(uri)?;
+        self.workspace_folders
+            .iter()
+            .filter(|folder| {
+                uri_to_path(&folder.uri)
+                    .is_some_and(|folder| path != folder && path.starts_with(folder))
+            })
+            .max_by_key(|folder| folder.uri.len())
+    }
+
+    // Keep root configs of the workspace folder up to date when files are created or deleted.
+    // Called for every file changed on disk, so cached config uris are cleared here too.
+    pub(crate) fn update_workspace_config(&mut self, uri: &str) {
+        self.config_uris.get_mut().clear();
+        let Some(path) =
+            uri_to_path(uri).filter(|path| path.file_name() == Some(ROOT_CONFIG.as_ref()))
+        else {
+            return;
+        };
+        let Some(folder_uri) = self.workspace_folder(uri).map(|folder| folder.uri.clone()) else {
+            return;
+        };
+        if let Some(folder) = self
+            .workspace_folders
+            .iter_mut()
+            .find(|folder| folder.uri == folder_uri)
+        {
+            folder.configs.retain(|config| !same_file(config, uri));
+            if path.is_file() {
+                folder.configs.extend(path_to_uri(&path));
+                folder.configs.sort();
+            }
+        }
+    }
+
+    // Return uri of the config which includes the document as a mixin: `lets.yaml` next to it
+    // or a root config of the same workspace folder, the closest one goes first.
+    // Return the document itself if it is not a mixin.
+    pub(crate) fn find_root_config(&self, uri: &str) -> String {
+        let includes = |root: &str| {
+            !same_file(root, uri)
+                && self.read_document(root).is_some_and(|doc| {
+                    Parser::new().get_mixins(&doc).iter().any(|mixin| {
+                        mixin_uri(root, mixin).is_some_and(|mixin| same_file(&mixin, uri))
+                    })
+                })
+        };
+        if let Some(root) = mixin_uri(uri, ROOT_CONFIG).filter(|root| includes(root)) {
+            return root;
+        }
+
+        let Some(folder) = self.workspace_folder(uri) else {
+            return uri.to_string();
+        };
+        let mut configs: Vec<&String> = folder
+            .configs
+            .iter()
+            .filter(|config| !same_file(config, uri))
+            .collect();
+        configs.sort_by_key(|config| std::cmp::Reverse(common_prefix_len(config, uri)));
+        configs
+            .into_iter()
+            .find(|config| {
+                self.get_config_uris(config)
+                    .iter()
+                    .any(|config_uri| same_file(config_uri, uri))
+            })
+            .cloned()
+            .unwrap_or_else(|| uri.to_string())
+    }
+
+    // Return uri and text of the config and all its mixins, the config goes first.
+    pub(crate) fn get_config_documents(&self, uri: &str) -> Vec<(String, String)> {
+        self.walk_config(uri)
+            .into_iter()
+            .filter_map(|(uri, doc)| Some((uri, doc?)))
+            .collect()
+    }
+
+    // Return uris of the config and all its mixins, including mixins missing on disk.
+    pub(crate) fn get_config_uris(&self, uri: &str) -> Vec<String> {
+        if let Some(uris) = self.config_uris.borrow().get(uri) {
+            return uris.clone();
+        }
+        let uris: Vec<String> = self
+            .walk_config(uri)
+            .into_iter()
+            .map(|(uri, _)| uri)
+            .collect();
+        self.config_uris
+            .borrow_mut()
+            .insert(uri.to_string(), uris.clone());
+        uris
+    }
+
+    fn walk_config(&self, uri: &str) -> Vec<(String, Option<String>)> {
+        let parser = Parser::new();
+        let mut documents = vec![];
+        let mut queue = vec![uri.to_string()];
+        while let Some(current) = queue.pop() {
+            if documents.iter().any(|(uri, _)| *uri == current) {
+                continue;
+            }
+            let doc = self.read_document(&current);
+            for mixin in doc.iter().flat_map(|doc| parser.get_mixins(doc)).rev() {
+                queue.extend(mixin_uri(&current, &mixin));
+            }
+            documents.push((current, doc));
+        }
+        documents
+    }
+
+    // Whether some mixins of the config can not be read: remote ones or missing on disk.
+    // Commands used in the config may be defined there.
+    pub(crate) fn has_unresolved_mixins(&self, uri: &str) -> bool {
+        let parser = Parser::new();
+        self.walk_config(uri).iter().any(|(_, doc)| match doc {
+            Some(doc) => !parser.get_remote_mixins(doc).is_empty(),
+            None => true,
+        })
+    }
+
+    // Files diagnostics of the document depend on: the config next to the document,
+    // the config the document belongs to and its mixins.
+    pub(crate) fn dependency_files(&self, uri: &str) -> Vec<String> {
+        let mut files = self.get_config_uris(&self.find_root_config(uri));
+        files.extend(mixin_uri(uri, ROOT_CONFIG));
+        files
+    }
+
+    // Return names of all commands available in the config the document belongs to.
+    pub(crate) fn get_known_commands(&self, uri: &str) -> Vec<String> {
+        let parser = Parser::new();
+        self.get_config_documents(&self.find_root_config(uri))
+            .iter()
+            .flat_map(|(_, doc)| parser.get_commands(doc))
+            .map(|command| command.name)
+            .collect()
+    }
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count()
+}
+
+fn scan_workspace_folder(uri: &str) -> WorkspaceFolder {
+    let mut configs = vec![];
+    if let Some(path) = uri_to_path(uri) {
+        find_configs(&path, 0, &mut configs);
+    }
+    configs.sort();
+    WorkspaceFolder {
+        uri: uri.to_string(),
+        configs,
+    }
+}
+
+// Collect root configs in the directory, hidden directories and `SKIPPED_DIRS` are skipped.
+fn find_configs(dir: &Path, depth: usize, configs: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if depth < MAX_SEARCH_DEPTH
+                && !name.starts_with('.')
+                && !SKIPPED_DIRS.contains(&name.as_ref())
+            {
+                find_configs(&path, depth + 1, configs);
+            }
+        } else if name == ROOT_CONFIG {
+            configs.extend(path_to_uri(&path));
+        }
+    }
+}
+
+// Construct uri of the mixin relative to the config uri.
+// uri: config URI in format file://path/to/file
+// filename: mixin filename, e.g. "lets.my.yaml"
+pub(crate) fn mixin_uri(uri: &str, filename: &str) -> Option<String> {
+    path_to_uri(&uri_to_path(uri)?.parent()?.join(filename))
+}
+
+// Path of the file uri with percent-encoded characters decoded.
+pub(crate) fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    url::Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+// File uri of the absolute path, special characters are percent-encoded.
+pub(crate) fn path_to_uri(path: &Path) -> Option<String> {
+    url::Url::from_file_path(path).ok().map(String::from)
+}
+
+// Whether the uris point to the same file, clients may encode characters differently.
+pub(crate) fn same_file(a: &str, b: &str) -> bool {
+    a == b || matches!((uri_to_path(a), uri_to_path(b)), (Some(a), Some(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_known_commands_with_mixins() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = format!("file://{}/lets.yaml", dir.path().display());
+        let mixin = format!("file://{}/lets.build.yaml", dir.path().display());
+        std::fs::write(
+            dir.path().join("lets.build.yaml"),
+            "commands:\n  build:\n    cmd: echo Build\n",
+        )
+        .unwrap();
+
+        let mut state = State::new();
+        state.add_document(
+            root.clone(),
+            "mixins:\n  - lets.build.yaml\n  - -lets.missing.yaml\ncommands:\n  test:\n    cmd: echo Test\n"
+                .to_string(),
+        );
+
+        assert_eq!(state.get_known_commands(&root), vec!["test", "build"]);
+        assert_eq!(state.find_root_config(&mixin), root);
+        assert_eq!(state.get_known_commands(&mixin), vec!["test", "build"]);
+        // optional mixin is missing on disk
+        assert!(state.has_unresolved_mixins(&root));
+    }
+
+    #[test]
+    fn test_config_uris_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let uri = |path: &str| path_to_uri(&dir.path().join(path)).unwrap();
+        std::fs::write(dir.path().join("lets.build.yaml"), "commands: {}\n").unwrap();
+
+        let mut state = State::new();
+        state.add_document(
+            uri("lets.yaml"),
+            "mixins: [lets.build.yaml]\ncommands: {}\n".to_string(),
+        );
+        assert_eq!(
+            state.get_config_uris(&uri("lets.yaml")),
+            vec![uri("lets.yaml"), uri("lets.build.yaml")]
+        );
+
+        // mixins of files on disk are re-read once the change is reported
+        std::fs::write(
+            dir.path().join("lets.build.yaml"),
+            "mixins: [lets.docker.yaml]\ncommands: {}\n",
+        )
+        .unwrap();
+        assert_eq!(state.get_config_uris(&uri("lets.yaml")).len(), 2);
+        state.update_workspace_config(&uri("lets.build.yaml"));
+        assert_eq!(
+            state.get_config_uris(&uri("lets.yaml")),
+            vec![
+                uri("lets.yaml"),
+                uri("lets.build.yaml"),
+                uri("lets.docker.yaml")
+            ]
+        );
+
+        state.update_document(
+            uri("lets.yaml"),
+            "commands:\n  test:\n    cmd: echo\n".to_string(),
+        );
+        assert_eq!(
+            state.get_config_uris(&uri("lets.yaml")),
+            vec![uri("lets.yaml")]
+        );
+    }
+
+    #[test]
+    fn test_has_unresolved_mixins() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = format!("file://{}/lets.yaml", dir.path().display());
+        std::fs::write(dir.path().join("lets.build.yaml"), "commands: {}\n").unwrap();
+
+        let mut state = State::new();
+        state.add_document(
+            root.clone(),
+            "mixins:\n  - \"lets.build.yaml\"\ncommands: {}\n".to_string(),
+        );
+        assert!(!state.has_unresolved_mixins(&root));
+
+        state.update_document(
+            root.clone(),
+            "mixins:\n  - lets.build.yaml\n  - url: https://example.com/lets.yaml\ncommands: {}\n"
+                .to_string(),
+        );
+        assert!(state.has_unresolved_mixins(&root));
+    }
+
+    #[test]
+    fn test_workspace_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, text: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write(
+            "services/api/lets.yaml",
+            "mixins: [.lets/db.yaml]\ncommands:\n  serve-api:\n    cmd: echo",
+        );
+        write(
+            "services/api/.lets/db.yaml",
+            "commands:\n  migrate:\n    cmd: echo",
+        );
+        write(
+            "services/web/lets.yaml",
+            "commands:\n  serve-web:\n    cmd: echo",
+        );
+        write("node_modules/pkg/lets.yaml", "commands: {}");
+        write("target/debug/lets.yaml", "commands: {}");
+        write("vendor/pkg/lets.yaml", "commands: {}");
+        let uri = |path: &str| format!("file://{}/{}", dir.path().display(), path);
+
+        let mut state = State::new();
+        state.add_workspace_folder(&format!("file://{}/", dir.path().display()));
+        assert_eq!(
+            state.workspace_folders[0].configs,
+            vec![uri("services/api/lets.yaml"), uri("services/web/lets.yaml")]
+        );
+
+        let mixin = uri("services/api/.lets/db.yaml");
+        assert_eq!(
+            state.find_root_config(&mixin),
+            uri("services/api/lets.yaml")
+        );
+        assert_eq!(
+            state.get_known_commands(&mixin),
+            vec!["serve-api", "migrate"]
+        );
+        assert_eq!(
+            state.get_known_commands(&uri("services/web/lets.yaml")),
+            vec!["serve-web"]
+        );
+
+        std::fs::remove_file(dir.path().join("services/api/lets.yaml")).unwrap();
+        state.update_workspace_config(&uri("services/api/lets.yaml"));
+        assert_eq!(
+            state.workspace_folders[0].configs,
+            vec![uri("services/web/lets.yaml")]
+        );
+        assert_eq!(state.find_root_config(&mixin), mixin);
+
+        state.remove_workspace_folder(&format!("file://{}", dir.path().display()));
+        assert!(state.workspace_folders.is_empty());
+    }
+
+    #[test]
+    fn test_workspace_folder_with_encoded_uris() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("my project/сервис");
+        std::fs::create_dir_all(project.join(".lets")).unwrap();
+        std::fs::write(
+            project.join("lets.yaml"),
+            "mixins: [.lets/db.yaml]\ncommands: {}",
+        )
+        .unwrap();
+        std::fs::write(project.join(".lets/db.yaml"), "commands: {}").unwrap();
+        let root = path_to_uri(&project.join("lets.yaml")).unwrap();
+        let mixin = path_to_uri(&project.join(".lets/db.yaml")).unwrap();
+        assert!(root.ends_with("/my%20project/%D1%81%D0%B5%D1%80%D0%B2%D0%B8%D1%81/lets.yaml"));
+
+        let mut state = State::new();
+        state.add_workspace_folder(&path_to_uri(dir.path()).unwrap());
+        assert_eq!(state.workspace_folders[0].configs, vec![root.clone()]);
+        assert_eq!(state.find_root_config(&mixin), root);
+        // not encoded uri points to the same file
+        let raw = format!("file://{}", project.join(".lets/db.yaml").display());
+        assert_eq!(state.find_root_config(&raw), root);
+
+        std::fs::remove_file(project.join("lets.yaml")).unwrap();
+        state.update_workspace_config(&format!("file://{}", project.join("lets.yaml").display()));
+        assert!(state.workspace_folders[0].configs.is_empty());
+    }
+
+    #[test]
+    fn test_workspace_folder_scanned_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lets.yaml"), "commands: {}").unwrap();
+        let folder = path_to_uri(dir.path()).unwrap();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = State::new();
+        state.folder_scans = Some(sender);
+        state.add_workspace_folder(&folder);
+        assert!(state.workspace_folders[0].configs.is_empty());
+
+        let scanned = receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(
+            scanned.configs,
+            vec![path_to_uri(&dir.path().join("lets.yaml")).unwrap()]
+        );
+        assert!(state.set_workspace_folder(scanned.clone()));
+        assert_eq!(state.workspace_folders, vec![scanned.clone()]);
+
+        state.remove_workspace_folder(&folder);
+        assert!(!state.set_workspace_folder(scanned));
+    }
 }
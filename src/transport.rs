@@ -0,0 +1,224 @@
+use std::io;
+#[cfg(unix)]
+use std::io::BufReader;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::thread::JoinHandle;
+
+#[cfg(unix)]
+use crossbeam_channel::bounded;
+use lsp_server::Connection;
+#[cfg(unix)]
+use lsp_server::Message;
+
+// How the server talks to the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Stdio,
+    // listen on localhost
+    Tcp(u16),
+    // listen on unix socket
+    #[cfg(unix)]
+    Socket(PathBuf),
+}
+
+// Reader and writer threads of the connection.
+pub enum IoThreads {
+    Lsp(lsp_server::IoThreads),
+    #[cfg(unix)]
+    Unix {
+        reader: JoinHandle<io::Result<()>>,
+        writer: JoinHandle<io::Result<()>>,
+    },
+}
+
+impl IoThreads {
+    pub fn join(self) -> io::Result<()> {
+        match self {
+            IoThreads::Lsp(threads) => threads.join(),
+            #[cfg(unix)]
+            IoThreads::Unix { reader, writer } => {
+                reader
+                    .join()
+                    .map_err(|_| io::Error::other("reader thread panicked"))??;
+                writer
+                    .join()
+                    .map_err(|_| io::Error::other("writer thread panicked"))?
+            }
+        }
+    }
+}
+
+impl Transport {
+    // Wait for the client to connect, for sockets only one client is accepted.
+    pub fn connect(&self) -> io::Result<(Connection, IoThreads)> {
+        match self {
+            Transport::Stdio => {
+                let (connection, threads) = Connection::stdio();
+                Ok((connection, IoThreads::Lsp(threads)))
+            }
+            Transport::Tcp(port) => {
+                log::info!("Listening on 127.0.0.1:{}", port);
+                let (connection, threads) = Connection::listen(("127.0.0.1", *port))?;
+                Ok((connection, IoThreads::Lsp(threads)))
+            }
+            #[cfg(unix)]
+            Transport::Socket(path) => {
+                log::info!("Listening on {}", path.display());
+                let listener = bind_unix(path)?;
+                let (stream, _) = listener.accept()?;
+                Ok(unix_connection(stream)?)
+            }
+        }
+    }
+}
+
+// Remove socket left by a previous run, binding fails otherwise.
+// Any other file at the path is an error, e.g. `--socket lets.yaml`.
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if UnixStream::connect(path).is_err() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    UnixListener::bind(path)
+}
+
+// Same as `lsp_server` socket transport, but over unix socket.
+#[cfg(unix)]
+fn unix_connection(stream: UnixStream) -> io::Result<(Connection, IoThreads)> {
+    let (reader_sender, receiver) = bounded::<Message>(0);
+    let (sender, writer_receiver) = bounded::<Message>(0);
+
+    let read_stream = stream.try_clone()?;
+    let reader = std::thread::spawn(move || {
+        let mut buf_read = BufReader::new(read_stream);
+        while let Some(msg) = Message::read(&mut buf_read)? {
+            let is_exit = matches!(&msg, Message::Notification(n) if n.method == "exit");
+            if reader_sender.send(msg).is_err() || is_exit {
+                break;
+            }
+        }
+        Ok(())
+    });
+    let mut write_stream = stream;
+    let writer = std::thread::spawn(move || {
+        writer_receiver
+            .into_iter()
+            .try_for_each(|msg| msg.write(&mut write_stream))
+    });
+
+    Ok((
+        Connection { sender, receiver },
+        IoThreads::Unix { reader, writer },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::{Message, Notification, Request, RequestId, Response};
+    use std::io::{BufReader, Read, Write};
+
+    // Answer a single request with "pong" and wait for exit.
+    fn serve(transport: Transport) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let (connection, threads) = transport.connect().unwrap();
+            let Message::Request(req) = connection.receiver.recv().unwrap() else {
+                panic!("expected request");
+            };
+            connection
+                .sender
+                .send(Message::Response(Response::new_ok(req.id, "pong")))
+                .unwrap();
+            let Message::Notification(notf) = connection.receiver.recv().unwrap() else {
+                panic!("expected exit");
+            };
+            assert_eq!(notf.method, "exit");
+            drop(connection);
+            threads.join().unwrap();
+        })
+    }
+
+    fn ping<S: Read + Write>(mut stream: S, reader: impl Read) {
+        Message::Request(Request::new(RequestId::from(1), "ping".to_string(), ()))
+            .write(&mut stream)
+            .unwrap();
+        let mut reader = BufReader::new(reader);
+        let Some(Message::Response(resp)) = Message::read(&mut reader).unwrap() else {
+            panic!("expected response");
+        };
+        assert_eq!(resp.result, Some(serde_json::json!("pong")));
+        Message::Notification(Notification::new("exit".to_string(), ()))
+            .write(&mut stream)
+            .unwrap();
+    }
+
+    fn retry<T>(connect: impl Fn() -> io::Result<T>) -> T {
+        loop {
+            match connect() {
+                Ok(stream) => break stream,
+                Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tcp_transport() {
+        let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = serve(Transport::Tcp(port));
+
+        let stream = retry(|| std::net::TcpStream::connect(("127.0.0.1", port)));
+        let reader = stream.try_clone().unwrap();
+        ping(stream, reader);
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_transport() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lets_ls.sock");
+        // stale socket must not prevent the server from starting
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let server = serve(Transport::Socket(path.clone()));
+        let stream = retry(|| UnixStream::connect(&path));
+        let reader = stream.try_clone().unwrap();
+        ping(stream, reader);
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_transport_keeps_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lets.yaml");
+        std::fs::write(&path, "commands: {}\n").unwrap();
+
+        let err = bind_unix(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "commands: {}\n");
+    }
+}
@@ -1,15 +1,18 @@
-use lsp_types::Position;
+use lsp_types::{Position, Range};
 use streaming_iterator::StreamingIterator;
 use tree_sitter::{Node, Point, Query, QueryCursor};
 
+use crate::shell::Script;
+
 #[derive(Debug)]
 pub enum PositionType {
     Mixins,
     Depends,
+    Script,
     None,
 }
 
-fn is_cursor_within_node(node: &Node, pos: &lsp_types::Position) -> bool {
+pub(crate) fn is_cursor_within_node(node: &Node, pos: &lsp_types::Position) -> bool {
     is_cursor_within_node_points(&node.start_position(), &node.end_position(), pos)
 }
 
@@ -39,13 +42,89 @@ fn is_cursor_at_line(node: &Node, pos: &lsp_types::Position) -> bool {
     pos.line as usize == start_point.row && pos.line as usize == end_point.row
 }
 
-fn get_node_text<'a>(node: &Node, text: &'a str) -> Option<&'a str> {
+pub(crate) fn get_node_text<'a>(node: &Node, text: &'a str) -> Option<&'a str> {
     if let Ok(value) = node.utf8_text(text.as_bytes()) {
         return Some(value);
     }
     None
 }
 
+// Block values end at the start of the next line, return the row with the last character.
+pub(crate) fn last_row(node: &Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+pub fn node_range(node: &Node) -> Range {
+    let start = node.start_position();
+    let end = node.end_position();
+    Range::new(
+        Position::new(start.row as u32, start.column as u32),
+        Position::new(end.row as u32, end.column as u32),
+    )
+}
+
+// Content indentation of a block scalar: leading spaces of its first non-empty line.
+// Tabs and other whitespace after them are a part of the content.
+pub(crate) fn block_scalar_indent<'a>(mut lines: impl Iterator<Item = &'a str>) -> usize {
+    lines
+        .find(|line| !line.trim_start_matches(' ').is_empty())
+        .map_or(0, |line| line.len() - line.trim_start_matches(' ').len())
+}
+
+// Strip up to `indent` leading spaces of a block scalar line.
+pub(crate) fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
+
+// Return the value of a scalar node with quotes and block scalar header stripped.
+// node: flow_node or block_node wrapping a scalar
+pub fn get_scalar_value(node: &Node, text: &str) -> Option<String> {
+    let scalar = if node.kind() == "flow_node" || node.kind() == "block_node" {
+        node.named_child(0)?
+    } else {
+        *node
+    };
+    let raw = get_node_text(&scalar, text)?;
+    match scalar.kind() {
+        "plain_scalar" => Some(raw.to_string()),
+        "double_quote_scalar" => Some(raw[1..raw.len() - 1].replace("\\\"", "\"")),
+        "single_quote_scalar" => Some(raw[1..raw.len() - 1].replace("''", "'")),
+        "block_scalar" => {
+            let body = raw.split_once('\n').map_or("", |(_, body)| body);
+            let indent = block_scalar_indent(body.lines());
+            let lines: Vec<&str> = body
+                .lines()
+                .map(|line| strip_indent(line, indent))
+                .collect();
+            Some(lines.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+fn is_env_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Return partially typed env variable name if cursor is right after `$`, `${` or `$NA`.
+pub fn env_prefix_before_cursor(line: &str, char_index: usize) -> Option<&str> {
+    if char_index > line.len() || !line.is_char_boundary(char_index) {
+        return None;
+    }
+    let before = word_before_cursor(line, char_index, |c| !is_env_name_char(c));
+    let prefix = &line[..char_index - before.len()];
+    if prefix.ends_with('$') || prefix.ends_with("${") {
+        return Some(before);
+    }
+    None
+}
+
 pub fn word_before_cursor(line: &str, char_index: usize, predicate: fn(c: char) -> bool) -> &str {
     if char_index == 0 || char_index > line.len() {
         return "";
@@ -79,6 +158,12 @@ pub fn word_after_cursor(line: &str, char_index: usize, predicate: fn(c: char) -
 
 pub struct Parser {}
 
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser {
     pub fn new() -> Self {
         Self {}
@@ -94,6 +179,11 @@ impl Parser {
         parser
     }
 
+    pub fn parse(&self, text: &str) -> tree_sitter::Tree {
+        let mut parser = self.new_parser();
+        parser.parse(text, None).expect("could not parse text")
+    }
+
     fn new_query(&self, query: &str) -> tree_sitter::Query {
         let language = tree_sitter_yaml::LANGUAGE.into();
         Query::new(&language, query).expect("could not create query")
@@ -104,10 +194,76 @@ impl Parser {
             return PositionType::Mixins;
         } else if self.is_depends_node(doc, pos) {
             return PositionType::Depends;
+        } else if self.is_script_node(doc, pos) {
+            return PositionType::Script;
         }
         PositionType::None
     }
 
+    // Check if cursor is inside a shell script, see `get_scripts`.
+    pub fn is_script_node(&self, text: &str, pos: &lsp_types::Position) -> bool {
+        self.get_scripts(text)
+            .iter()
+            .any(|script| script.contains(pos))
+    }
+
+    // Return env variable name referenced under the cursor inside a script.
+    pub fn get_env_reference(&self, text: &str, pos: &lsp_types::Position) -> Option<String> {
+        let script = self
+            .get_scripts(text)
+            .into_iter()
+            .find(|script| script.contains(pos))?;
+        script
+            .variable_references()
+            .into_iter()
+            .find(|var| var.range.start <= *pos && *pos <= var.range.end)
+            .map(|var| var.name)
+    }
+
+    // Return all shell scripts in the document: top-level `before`/`init`,
+    // command `cmd`/`after` (including list and parallel map forms),
+    // `sh:` env values and `eval_env` values.
+    pub fn get_scripts(&self, text: &str) -> Vec<Script> {
+        let tree = self.parse(text);
+        let mut scripts = vec![];
+        let Some(root) = find_root_mapping(&tree.root_node()) else {
+            return scripts;
+        };
+
+        for key in ["before", "init"] {
+            if let Some(value) = find_mapping_value(&root, text, key) {
+                collect_scripts(&value, text, key, &mut scripts);
+            }
+        }
+        collect_env_scripts(&root, text, &mut scripts);
+
+        let Some(commands) = find_mapping_value(&root, text, "commands")
+            .and_then(|commands| commands.named_child(0))
+            .filter(|commands| commands.kind() == "block_mapping")
+        else {
+            return scripts;
+        };
+        let mut cursor = commands.walk();
+        for command in commands.named_children(&mut cursor) {
+            let Some(body) = command
+                .child_by_field_name("value")
+                .and_then(|value| value.named_child(0))
+                .filter(|body| body.kind() == "block_mapping")
+            else {
+                continue;
+            };
+            for key in ["cmd", "after"] {
+                if let Some(value) = find_mapping_value(&body, text, key) {
+                    collect_scripts(&value, text, key, &mut scripts);
+                }
+            }
+            collect_env_scripts(&body, text, &mut scripts);
+        }
+
+        scripts.sort_by_key(|script| script.range.start);
+        scripts
+    }
+
     pub fn is_mixin_root_node(&self, text: &str, pos: &lsp_types::Position) -> bool {
         let mut parser = self.new_parser();
         let query = self.new_query(
@@ -272,6 +428,7 @@ impl Parser {
                 if c.index == cmd_key_idx {
                     let command = Command {
                         name: get_node_text(&c.node, doc).unwrap().to_string(),
+                        range: node_range(&c.node),
                     };
                     commands.push(command);
                 }
@@ -317,24 +474,498 @@ impl Parser {
                     if !is_cursor_within_node(&c.node, pos) {
                         continue;
                     }
-                    return c
-                        .node
-                        .child_by_field_name("key")
-                        .and_then(|n| get_node_text(&n, doc))
-                        .map(|name| Command {
+                    return c.node.child_by_field_name("key").and_then(|n| {
+                        get_node_text(&n, doc).map(|name| Command {
                             name: name.to_string(),
-                        });
+                            range: node_range(&n),
+                        })
+                    });
                 }
             }
         }
 
         None
     }
+
+    // Return variables declared in top-level `env` and `eval_env`.
+    pub fn get_global_env(&self, doc: &str) -> Vec<EnvVar> {
+        let mut parser = self.new_parser();
+        let query = self.new_query(
+            r#"
+        (stream
+        (document
+            (block_node
+            (block_mapping
+                (block_mapping_pair
+                key: (flow_node(plain_scalar(string_scalar)@section))
+                value: (block_node
+                        (block_mapping
+                            (block_mapping_pair)@var)))))))
+        (#any-of? @section "env" "eval_env")
+        "#,
+        );
+
+        let tree = parser.parse(doc, None).expect("could not parse text");
+        let root = tree.root_node();
+
+        let mut cursor_qry = QueryCursor::new();
+        let mut matches = cursor_qry.matches(&query, root, doc.as_bytes());
+
+        let section_idx = query.capture_index_for_name("section").unwrap();
+        let var_idx = query.capture_index_for_name("var").unwrap();
+
+        let mut env = vec![];
+        while let Some(m) = matches.next() {
+            let section = m
+                .captures
+                .iter()
+                .find(|c| c.index == section_idx)
+                .and_then(|c| get_node_text(&c.node, doc));
+            let is_eval = section == Some("eval_env");
+            for c in m.captures {
+                if c.index == var_idx {
+                    if let Some(var) = env_var_from_pair(&c.node, doc, is_eval) {
+                        env.push(var);
+                    }
+                }
+            }
+        }
+        env
+    }
+
+    // Return variables declared in `env` of the given command.
+    pub fn get_command_env(&self, doc: &str, command: &str) -> Vec<EnvVar> {
+        let mut parser = self.new_parser();
+        let query = self.new_query(
+            r#"
+        (stream
+        (document
+            (block_node
+            (block_mapping
+                (block_mapping_pair
+                key: (flow_node(plain_scalar(string_scalar)@commands))
+                value: (block_node
+                        (block_mapping
+                            (block_mapping_pair
+                            key: (flow_node(plain_scalar(string_scalar)@command))
+                            value: (block_node
+                                    (block_mapping
+                                        (block_mapping_pair
+                                        key: (flow_node(plain_scalar(string_scalar)@section))
+                                        value: (block_node
+                                                (block_mapping
+                                                    (block_mapping_pair)@var)))))))))))))
+        (#eq? @commands "commands")
+        (#eq? @section "env")
+        "#,
+        );
+
+        let tree = parser.parse(doc, None).expect("could not parse text");
+        let root = tree.root_node();
+
+        let mut cursor_qry = QueryCursor::new();
+        let mut matches = cursor_qry.matches(&query, root, doc.as_bytes());
+
+        let command_idx = query.capture_index_for_name("command").unwrap();
+        let var_idx = query.capture_index_for_name("var").unwrap();
+
+        let mut env = vec![];
+        while let Some(m) = matches.next() {
+            let is_command = m
+                .captures
+                .iter()
+                .any(|c| c.index == command_idx && get_node_text(&c.node, doc) == Some(command));
+            if !is_command {
+                continue;
+            }
+            for c in m.captures {
+                if c.index == var_idx {
+                    if let Some(var) = env_var_from_pair(&c.node, doc, false) {
+                        env.push(var);
+                    }
+                }
+            }
+        }
+        env
+    }
+
+    // Return file names listed in `mixins`.
+    // Leading `-` marks optional mixin and is stripped, remote mixins are skipped.
+    pub fn get_mixins(&self, doc: &str) -> Vec<String> {
+        let tree = self.parse(doc);
+        let Some(mixins) = find_root_mapping(&tree.root_node())
+            .and_then(|root| find_mapping_value(&root, doc, "mixins"))
+        else {
+            return vec![];
+        };
+        get_sequence_items(&mixins)
+            .iter()
+            .filter_map(|item| get_scalar_value(item, doc))
+            .map(|name| name.strip_prefix('-').map(str::to_string).unwrap_or(name))
+            .collect()
+    }
+
+    // Return value of the top-level `shell`.
+    pub fn get_shell(&self, doc: &str) -> Option<String> {
+        let tree = self.parse(doc);
+        let shell = find_root_mapping(&tree.root_node())
+            .and_then(|root| find_mapping_value(&root, doc, "shell"))?;
+        get_scalar_value(&shell, doc)
+    }
+
+    // Return urls of remote mixins, e.g. `- url: https://example.com/lets.yaml`.
+    pub fn get_remote_mixins(&self, doc: &str) -> Vec<String> {
+        let tree = self.parse(doc);
+        let Some(mixins) = find_root_mapping(&tree.root_node())
+            .and_then(|root| find_mapping_value(&root, doc, "mixins"))
+        else {
+            return vec![];
+        };
+        get_sequence_items(&mixins)
+            .iter()
+            .filter_map(|item| item.named_child(0))
+            .filter(|item| item.kind() == "block_mapping")
+            .filter_map(|item| find_mapping_value(&item, doc, "url"))
+            .filter_map(|url| get_scalar_value(&url, doc))
+            .collect()
+    }
+
+    // Return references to other commands from `depends` and `ref`.
+    pub fn get_command_references(&self, doc: &str) -> Vec<CommandReference> {
+        let tree = self.parse(doc);
+        let mut references = vec![];
+        let Some(commands) = find_root_mapping(&tree.root_node())
+            .and_then(|root| find_mapping_value(&root, doc, "commands"))
+            .and_then(|commands| commands.named_child(0))
+            .filter(|commands| commands.kind() == "block_mapping")
+        else {
+            return references;
+        };
+
+        let mut cursor = commands.walk();
+        for command in commands.named_children(&mut cursor) {
+            let Some(name) = get_pair_key(&command, doc) else {
+                continue;
+            };
+            let Some(body) = command
+                .child_by_field_name("value")
+                .and_then(|value| value.named_child(0))
+                .filter(|body| body.kind() == "block_mapping")
+            else {
+                continue;
+            };
+
+            let mut reference = |node: &Node, kind: ReferenceKind| {
+                if let Some(text) = get_scalar_value(node, doc) {
+                    references.push(CommandReference {
+                        name: text,
+                        range: node_range(node),
+                        command: name.to_string(),
+                        kind,
+                    });
+                }
+            };
+
+            if let Some(depends) = find_mapping_value(&body, doc, "depends") {
+                for item in get_sequence_items(&depends) {
+                    // `- name: cmd` items pass args to the dependency
+                    let item = match item.named_child(0) {
+                        Some(mapping)
+                            if matches!(mapping.kind(), "block_mapping" | "flow_mapping") =>
+                        {
+                            let mut cursor = mapping.walk();
+                            let pair = mapping
+                                .named_children(&mut cursor)
+                                .find(|pair| get_pair_key(pair, doc) == Some("name"));
+                            match pair.and_then(|pair| pair.child_by_field_name("value")) {
+                                Some(value) => value,
+                                None => continue,
+                            }
+                        }
+                        _ => item,
+                    };
+                    reference(&item, ReferenceKind::Depends);
+                }
+            }
+            if let Some(value) = find_mapping_value(&body, doc, "ref") {
+                reference(&value, ReferenceKind::Ref);
+            }
+        }
+        references
+    }
+
+    // Check if the command declares the key, e.g. `options`.
+    pub fn command_has_key(&self, doc: &str, command: &str, key: &str) -> bool {
+        let tree = self.parse(doc);
+        find_command_mapping(&tree.root_node(), doc, command)
+            .and_then(|mapping| find_mapping_value(&mapping, doc, key))
+            .is_some()
+    }
+
+    // Return names of checksum groups declared by the command.
+    // None if command has no `checksum`, empty list if checksum is a plain list of files.
+    pub fn get_command_checksum_names(&self, doc: &str, command: &str) -> Option<Vec<String>> {
+        let mut parser = self.new_parser();
+        let tree = parser.parse(doc, None).expect("could not parse text");
+        let command = find_command_mapping(&tree.root_node(), doc, command)?;
+        let checksum = find_mapping_value(&command, doc, "checksum")?;
+
+        let inner = checksum.named_child(0)?;
+        if inner.kind() != "block_mapping" && inner.kind() != "flow_mapping" {
+            return Some(vec![]);
+        }
+        let mut cursor = inner.walk();
+        let names = inner
+            .named_children(&mut cursor)
+            .filter_map(|pair| get_pair_key(&pair, doc))
+            .map(|name| name.to_string())
+            .collect();
+        Some(names)
+    }
+}
+
+// Return value node of `key` in block mapping.
+pub(crate) fn find_mapping_value<'t>(
+    mapping: &Node<'t>,
+    text: &str,
+    key: &str,
+) -> Option<Node<'t>> {
+    let mut cursor = mapping.walk();
+    let pair = mapping.named_children(&mut cursor).find(|pair| {
+        pair.kind() == "block_mapping_pair" && get_pair_key(pair, text) == Some(key)
+    })?;
+    pair.child_by_field_name("value")
+}
+
+// Return item nodes of block or flow sequence.
+// node: block_node or flow_node wrapping a sequence
+pub(crate) fn get_sequence_items<'t>(node: &Node<'t>) -> Vec<Node<'t>> {
+    let Some(sequence) = node.named_child(0) else {
+        return vec![];
+    };
+    let mut cursor = sequence.walk();
+    match sequence.kind() {
+        "flow_sequence" => sequence
+            .named_children(&mut cursor)
+            .filter(|item| item.kind() == "flow_node")
+            .collect(),
+        "block_sequence" => sequence
+            .named_children(&mut cursor)
+            .filter_map(|item| item.named_child(0))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// Return the top-level block mapping of the document.
+pub(crate) fn find_root_mapping<'t>(root: &Node<'t>) -> Option<Node<'t>> {
+    let document = root.named_child(0)?;
+    let block_node = document.named_child(0)?;
+    block_node
+        .named_child(0)
+        .filter(|mapping| mapping.kind() == "block_mapping")
+}
+
+// Return block mapping with the body of the command.
+pub(crate) fn find_command_mapping<'t>(
+    root: &Node<'t>,
+    text: &str,
+    command: &str,
+) -> Option<Node<'t>> {
+    let root_mapping = find_root_mapping(root)?;
+    let commands = find_mapping_value(&root_mapping, text, "commands")?.named_child(0)?;
+    find_mapping_value(&commands, text, command)?
+        .named_child(0)
+        .filter(|mapping| mapping.kind() == "block_mapping")
+}
+
+pub(crate) fn get_pair_key<'a>(pair: &Node, text: &'a str) -> Option<&'a str> {
+    pair.child_by_field_name("key")
+        .and_then(|key| get_node_text(&key, text))
+}
+
+// Collect scripts from `env` (`sh:` values) and `eval_env` of the mapping.
+fn collect_env_scripts(mapping: &Node, text: &str, scripts: &mut Vec<Script>) {
+    for key in ["env", "eval_env"] {
+        let Some(env) = find_mapping_value(mapping, text, key)
+            .and_then(|env| env.named_child(0))
+            .filter(|env| env.kind() == "block_mapping")
+        else {
+            continue;
+        };
+        let mut cursor = env.walk();
+        for pair in env.named_children(&mut cursor) {
+            let Some(value) = pair.child_by_field_name("value") else {
+                continue;
+            };
+            if key == "eval_env" {
+                scripts.extend(script_from_node(&value, text, key));
+                continue;
+            }
+            let sh = value
+                .named_child(0)
+                .filter(|inner| matches!(inner.kind(), "block_mapping" | "flow_mapping"))
+                .and_then(|inner| {
+                    let mut cursor = inner.walk();
+                    let pair = inner
+                        .named_children(&mut cursor)
+                        .find(|pair| get_pair_key(pair, text) == Some("sh"));
+                    pair.and_then(|pair| pair.child_by_field_name("value"))
+                });
+            if let Some(sh) = sh {
+                scripts.extend(script_from_node(&sh, text, "sh"));
+            }
+        }
+    }
+}
+
+// Collect scripts from a scalar, a list of scalars or a map of scalars (parallel cmd).
+fn collect_scripts(node: &Node, text: &str, key: &'static str, scripts: &mut Vec<Script>) {
+    match node.named_child(0).map(|inner| inner.kind()) {
+        Some("block_sequence") | Some("flow_sequence") => {
+            for item in get_sequence_items(node) {
+                scripts.extend(script_from_node(&item, text, key));
+            }
+        }
+        Some("block_mapping") | Some("flow_mapping") => {
+            let mapping = node.named_child(0).unwrap();
+            let mut cursor = mapping.walk();
+            for pair in mapping.named_children(&mut cursor) {
+                if let Some(value) = pair.child_by_field_name("value") {
+                    scripts.extend(script_from_node(&value, text, key));
+                }
+            }
+        }
+        _ => scripts.extend(script_from_node(node, text, key)),
+    }
+}
+
+// Build script from a scalar node, remembering where each script line starts.
+// Block scalars are dedented, quotes are stripped but escapes are kept
+// so that columns still match the document.
+// key: YAML key the script belongs to, e.g. `cmd` or `sh`
+fn script_from_node(node: &Node, text: &str, key: &'static str) -> Option<Script> {
+    let scalar = node.named_child(0)?;
+    let raw = get_node_text(&scalar, text)?;
+    let start = scalar.start_position();
+    let range = node_range(&scalar);
+
+    match scalar.kind() {
+        "block_scalar" => {
+            let body = raw.split_once('\n').map_or("", |(_, body)| body);
+            let indent = body
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            let mut lines = vec![];
+            let mut script = vec![];
+            for (i, line) in body.lines().enumerate() {
+                lines.push(Position::new((start.row + 1 + i) as u32, indent as u32));
+                script.push(line.get(indent..).unwrap_or(""));
+            }
+            Some(Script::new(key, script.join("\n"), range, lines))
+        }
+        "plain_scalar" | "double_quote_scalar" | "single_quote_scalar" => {
+            let (script, column) = if scalar.kind() == "plain_scalar" {
+                (raw, start.column)
+            } else {
+                (&raw[1..raw.len() - 1], start.column + 1)
+            };
+            let lines = (0..script.lines().count().max(1))
+                .map(|i| {
+                    let column = if i == 0 { column } else { 0 };
+                    Position::new((start.row + i) as u32, column as u32)
+                })
+                .collect();
+            Some(Script::new(key, script.to_string(), range, lines))
+        }
+        _ => None,
+    }
+}
+
+// Build env variable from `NAME: value` pair.
+// Values in `eval_env` are always shell expressions.
+fn env_var_from_pair(pair: &Node, text: &str, is_eval: bool) -> Option<EnvVar> {
+    let key = pair.child_by_field_name("key")?;
+    let name = get_node_text(&key, text)?.to_string();
+    let value_node = pair.child_by_field_name("value")?;
+
+    let value = if is_eval {
+        EnvValue::Sh(get_scalar_value(&value_node, text)?)
+    } else {
+        env_value(&value_node, text)?
+    };
+
+    Some(EnvVar {
+        name,
+        value,
+        range: node_range(&key),
+    })
+}
+
+// Parse env value: plain scalar, `sh: <script>` or `checksum: [files]`.
+fn env_value(node: &Node, text: &str) -> Option<EnvValue> {
+    let inner = node.named_child(0)?;
+    if !matches!(inner.kind(), "block_mapping" | "flow_mapping") {
+        return get_scalar_value(node, text).map(EnvValue::Literal);
+    }
+
+    let mut cursor = inner.walk();
+    let pair = inner
+        .named_children(&mut cursor)
+        .find(|n| matches!(n.kind(), "block_mapping_pair" | "flow_pair"))?;
+    let value = pair.child_by_field_name("value")?;
+    match get_pair_key(&pair, text)? {
+        "sh" => get_scalar_value(&value, text).map(EnvValue::Sh),
+        "checksum" => {
+            let files = get_sequence_items(&value)
+                .iter()
+                .filter_map(|item| get_scalar_value(item, text))
+                .collect();
+            Some(EnvValue::Checksum(files))
+        }
+        _ => None,
+    }
 }
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Command {
     pub name: String,
+    // range of the command name in the document
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    Depends,
+    Ref,
+}
+
+// Usage of a command name in `depends` or `ref` of another command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandReference {
+    pub name: String,
+    pub range: Range,
+    // command the reference belongs to
+    pub command: String,
+    pub kind: ReferenceKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvValue {
+    Literal(String),
+    Sh(String),
+    Checksum(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: EnvValue,
+    // range of the variable name in the document
+    pub range: Range,
 }
 
 #[cfg(test)]
@@ -551,9 +1182,11 @@ commands:
             vec![
                 Command {
                     name: "test".to_string(),
+                    range: Range::new(Position::new(4, 2), Position::new(4, 6)),
                 },
                 Command {
                     name: "test2".to_string(),
+                    range: Range::new(Position::new(6, 2), Position::new(6, 7)),
                 },
             ]
         );
@@ -578,6 +1211,7 @@ commands:
             command,
             Some(Command {
                 name: "test".to_string(),
+                range: Range::new(Position::new(4, 2), Position::new(4, 6)),
             })
         );
     }
@@ -605,7 +1239,324 @@ commands:
             command,
             Some(Command {
                 name: "test3".to_string(),
+                range: Range::new(Position::new(8, 2), Position::new(8, 7)),
             })
         );
     }
+
+    #[test]
+    fn test_detect_script_node() {
+        let doc = r#"
+shell: bash
+env:
+  cmd: not a script
+before: echo before
+commands:
+  test:
+    cmd: |
+      echo $FOO
+    after: echo after
+    description: echo description"#
+            .trim();
+
+        let tests = vec![
+            (Position::new(2, 10), false),
+            (Position::new(3, 10), true),
+            (Position::new(6, 9), true),
+            (Position::new(7, 8), true),
+            (Position::new(8, 14), true),
+            (Position::new(9, 20), false),
+        ];
+
+        let parser = Parser::new();
+        for (i, (pos, expect)) in tests.into_iter().enumerate() {
+            let result = parser.is_script_node(doc, &pos);
+            assert_eq!(
+                result, expect,
+                "Case {i}: expected {expect}, actual {result}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_scripts() {
+        let doc = r#"
+shell: bash
+env:
+  FOO:
+    sh: echo $HOME
+eval_env:
+  BAR: "echo bar"
+before: |
+  echo before
+  echo $FOO
+commands:
+  test:
+    cmd: [echo, $FOO]
+  parallel:
+    cmd:
+      one: echo one
+      two: |
+        echo two"#
+            .trim();
+
+        let parser = Parser::new();
+        let scripts: Vec<(String, Position)> = parser
+            .get_scripts(doc)
+            .into_iter()
+            .map(|script| {
+                let start = script.to_document_position(Point::new(0, 0));
+                (script.text, start)
+            })
+            .collect();
+        assert_eq!(
+            scripts,
+            vec![
+                ("echo $HOME".to_string(), Position::new(3, 8)),
+                ("echo bar".to_string(), Position::new(5, 8)),
+                ("echo before\necho $FOO".to_string(), Position::new(7, 2)),
+                ("echo".to_string(), Position::new(11, 10)),
+                ("$FOO".to_string(), Position::new(11, 16)),
+                ("echo one".to_string(), Position::new(14, 11)),
+                ("echo two".to_string(), Position::new(16, 8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_env_reference() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    cmd: |
+      echo $FOO '$NOPE'
+      # $COMMENT"#
+            .trim();
+
+        let tests = vec![
+            (Position::new(4, 13), Some("FOO".to_string())),
+            (Position::new(4, 18), None),
+            (Position::new(5, 10), None),
+        ];
+
+        let parser = Parser::new();
+        for (i, (pos, expect)) in tests.into_iter().enumerate() {
+            let result = parser.get_env_reference(doc, &pos);
+            assert_eq!(
+                result, expect,
+                "Case {i}: expected {expect:?}, actual {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_env_prefix_before_cursor() {
+        let tests = vec![
+            ("echo $", 6, Some("")),
+            ("echo ${", 7, Some("")),
+            ("echo $LE", 8, Some("LE")),
+            ("echo LE", 7, None),
+            ("echo $LE", 4, None),
+        ];
+        for (i, (line, char_index, expect)) in tests.into_iter().enumerate() {
+            let result = env_prefix_before_cursor(line, char_index);
+            assert_eq!(
+                result, expect,
+                "Case {i}: expected {expect:?}, actual {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_global_env() {
+        let doc = r#"
+shell: bash
+env:
+  FOO: bar
+  QUOTED: "quoted"
+  SH:
+    sh: echo sh
+  FLOW: {sh: "echo flow"}
+  SUM:
+    checksum: [a.txt, b.txt]
+eval_env:
+  EVAL: echo eval
+commands:
+  test:
+    env:
+      LOCAL: local
+    cmd: echo Test"#
+            .trim();
+
+        let parser = Parser::new();
+        let env: Vec<(String, EnvValue)> = parser
+            .get_global_env(doc)
+            .into_iter()
+            .map(|var| (var.name, var.value))
+            .collect();
+        assert_eq!(
+            env,
+            vec![
+                ("FOO".to_string(), EnvValue::Literal("bar".to_string())),
+                (
+                    "QUOTED".to_string(),
+                    EnvValue::Literal("quoted".to_string())
+                ),
+                ("SH".to_string(), EnvValue::Sh("echo sh".to_string())),
+                ("FLOW".to_string(), EnvValue::Sh("echo flow".to_string())),
+                (
+                    "SUM".to_string(),
+                    EnvValue::Checksum(vec!["a.txt".to_string(), "b.txt".to_string()])
+                ),
+                ("EVAL".to_string(), EnvValue::Sh("echo eval".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_command_env() {
+        let doc = r#"
+shell: bash
+env:
+  FOO: bar
+commands:
+  test:
+    env:
+      LOCAL: local
+      SCRIPT:
+        sh: |
+          echo one
+          echo two
+    cmd: echo Test
+  test2:
+    env:
+      OTHER: other
+    cmd: echo Test2"#
+            .trim();
+
+        let parser = Parser::new();
+        let env = parser.get_command_env(doc, "test");
+        assert_eq!(
+            env,
+            vec![
+                EnvVar {
+                    name: "LOCAL".to_string(),
+                    value: EnvValue::Literal("local".to_string()),
+                    range: Range::new(Position::new(6, 6), Position::new(6, 11)),
+                },
+                EnvVar {
+                    name: "SCRIPT".to_string(),
+                    value: EnvValue::Sh("echo one\necho two".to_string()),
+                    range: Range::new(Position::new(7, 6), Position::new(7, 12)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_mixins() {
+        let doc = r#"
+shell: bash
+mixins:
+  - lets.my.yaml
+  - -lets.optional.yaml
+  - "lets.quoted.yaml"
+  - '-lets.single.yaml'
+  - url: https://example.com/lets.remote.yaml
+    version: v1
+commands:
+  test:
+    cmd: echo Test"#
+            .trim();
+
+        let parser = Parser::new();
+        assert_eq!(
+            parser.get_mixins(doc),
+            vec![
+                "lets.my.yaml".to_string(),
+                "lets.optional.yaml".to_string(),
+                "lets.quoted.yaml".to_string(),
+                "lets.single.yaml".to_string(),
+            ]
+        );
+        assert_eq!(
+            parser.get_remote_mixins(doc),
+            vec!["https://example.com/lets.remote.yaml".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_get_command_references() {
+        let doc = r#"
+shell: bash
+commands:
+  test:
+    depends: [build, lint]
+    cmd: echo Test
+  deploy:
+    depends:
+      - test
+      - name: migrate
+        args: --fake
+    cmd: echo Deploy
+  release:
+    ref: deploy"#
+            .trim();
+
+        let parser = Parser::new();
+        let references: Vec<(String, String, ReferenceKind, Position)> = parser
+            .get_command_references(doc)
+            .into_iter()
+            .map(|r| (r.command, r.name, r.kind, r.range.start))
+            .collect();
+        let reference = |command: &str, name: &str, kind, line, character| {
+            (
+                command.to_string(),
+                name.to_string(),
+                kind,
+                Position::new(line, character),
+            )
+        };
+        assert_eq!(
+            references,
+            vec![
+                reference("test", "build", ReferenceKind::Depends, 3, 14),
+                reference("test", "lint", ReferenceKind::Depends, 3, 21),
+                reference("deploy", "test", ReferenceKind::Depends, 7, 8),
+                reference("deploy", "migrate", ReferenceKind::Depends, 8, 14),
+                reference("release", "deploy", ReferenceKind::Ref, 12, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_command_checksum_names() {
+        let doc = r#"
+shell: bash
+commands:
+  plain:
+    checksum: [a.txt]
+    cmd: echo Plain
+  named:
+    checksum:
+      deps:
+        - requirements.txt
+      node: [package.json]
+    cmd: echo Named
+  none:
+    cmd: echo None"#
+            .trim();
+
+        let parser = Parser::new();
+        assert_eq!(
+            parser.get_command_checksum_names(doc, "plain"),
+            Some(vec![])
+        );
+        assert_eq!(
+            parser.get_command_checksum_names(doc, "named"),
+            Some(vec!["deps".to_string(), "node".to_string()])
+        );
+        assert_eq!(parser.get_command_checksum_names(doc, "none"), None);
+    }
 }